@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 use crate::{ExecutionStatus, wasm::AgentResult};
 
@@ -19,6 +20,19 @@ pub struct AgentConfig {
     pub timeout_seconds: Option<u64>,
     pub max_retries: Option<u32>,
     pub environment: Option<std::collections::HashMap<String, String>>,
+    /// Fuel budget for this execution, on either backend; `None` falls back to
+    /// `wasm::DEFAULT_FUEL_BUDGET`. Exhausting it traps the guest with a
+    /// deterministic "gas limit exceeded" error instead of running unbounded.
+    pub fuel_limit: Option<u64>,
+    /// Names of host-function capabilities this agent is granted (e.g.
+    /// `"llm"`, `"kv"`, `"http"`). `None`/unrecognized names grant nothing;
+    /// an agent that tries to call an import it wasn't issued traps.
+    pub capabilities: Option<Vec<String>>,
+    /// Which WASM engine should run this agent: `"wasmtime"` (the default,
+    /// JIT-compiled) or `"wasmi"` (a pure interpreter, slower but
+    /// byte-for-byte deterministic and JIT-free). `None`/unrecognized names
+    /// fall back to wasmtime.
+    pub backend: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +46,31 @@ pub struct AgentResponse {
     pub completed_at: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
+/// A single state transition for an execution, broadcast to anyone watching
+/// it over SSE/WebSocket. There's no incremental output event: the sandbox
+/// only produces a result once `execute` returns, so `Completed` carries the
+/// entire output in one shot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEvent {
+    Status {
+        status: ExecutionStatus,
+        started_at: u64,
+    },
+    Completed {
+        result: AgentResult,
+        completed_at: u64,
+    },
+    Failed {
+        error: String,
+        completed_at: u64,
+    },
+    Cancelled {
+        completed_at: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentExecution {
     pub id: String,
     pub request: AgentRequest,
@@ -61,39 +99,105 @@ impl AgentExecution {
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self, events: &broadcast::Sender<ExecutionEvent>) {
         self.status = ExecutionStatus::Running;
         self.started_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
+
+        let _ = events.send(ExecutionEvent::Status {
+            status: self.status.clone(),
+            started_at: self.started_at,
+        });
     }
 
-    pub fn complete_success(&mut self, result: AgentResult) {
+    pub fn complete_success(&mut self, result: AgentResult, events: &broadcast::Sender<ExecutionEvent>) {
         self.status = ExecutionStatus::Completed;
-        self.result = Some(result);
-        self.completed_at = Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
+        self.result = Some(result.clone());
+        let completed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.completed_at = Some(completed_at);
+
+        let _ = events.send(ExecutionEvent::Completed { result, completed_at });
     }
 
-    pub fn complete_error(&mut self, error: String) {
+    pub fn complete_error(&mut self, error: String, events: &broadcast::Sender<ExecutionEvent>) {
         self.status = ExecutionStatus::Failed;
-        self.error = Some(error);
-        self.completed_at = Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
+        self.error = Some(error.clone());
+        let completed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.completed_at = Some(completed_at);
+
+        let _ = events.send(ExecutionEvent::Failed { error, completed_at });
+    }
+
+    /// Like `complete_error`, but for a guest that actually ran and produced
+    /// an `AgentResult { success: false, .. }` (a trap, fuel exhaustion, or
+    /// guest-side timeout) rather than an infra-level `Err`. Keeps the
+    /// computed `result` (and its `ExecutionMetrics`) around instead of
+    /// discarding it, so a failed execution still reports gas/memory/call
+    /// counts via `AgentResponse` and `total_memory_usage_bytes`.
+    pub fn complete_failure(&mut self, result: AgentResult, events: &broadcast::Sender<ExecutionEvent>) {
+        self.status = ExecutionStatus::Failed;
+        self.error = result.error.clone();
+        self.result = Some(result);
+        let completed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.completed_at = Some(completed_at);
+
+        let error = self.error.clone().unwrap_or_default();
+        let _ = events.send(ExecutionEvent::Failed { error, completed_at });
+    }
+
+    pub fn cancel(&mut self, events: &broadcast::Sender<ExecutionEvent>) {
+        tracing::info!("🚫 Cancelling agent execution: {}", self.id);
+        self.status = ExecutionStatus::Cancelled;
+        let completed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.completed_at = Some(completed_at);
+
+        let _ = events.send(ExecutionEvent::Cancelled { completed_at });
     }
 
-    pub async fn cancel(&self) {
-        // TODO: Implement cancellation logic
-        tracing::info!("ðŸš« Cancelling agent execution: {}", self.id);
+    /// The event a newly-subscribed SSE/WebSocket client should see immediately,
+    /// so it isn't stuck waiting for the next state transition.
+    pub fn snapshot_event(&self) -> ExecutionEvent {
+        match &self.status {
+            // A `Completed` row should always carry a result, but this feeds
+            // a network-facing SSE/WebSocket handler: degrade to a `Failed`
+            // event instead of panicking if one ever doesn't (e.g. a
+            // rehydrated or hand-written store row).
+            ExecutionStatus::Completed => match self.result.clone() {
+                Some(result) => ExecutionEvent::Completed {
+                    result,
+                    completed_at: self.completed_at.unwrap_or(self.started_at),
+                },
+                None => ExecutionEvent::Failed {
+                    error: "execution marked completed but has no recorded result".to_string(),
+                    completed_at: self.completed_at.unwrap_or(self.started_at),
+                },
+            },
+            ExecutionStatus::Failed => ExecutionEvent::Failed {
+                error: self.error.clone().unwrap_or_default(),
+                completed_at: self.completed_at.unwrap_or(self.started_at),
+            },
+            ExecutionStatus::Cancelled => ExecutionEvent::Cancelled {
+                completed_at: self.completed_at.unwrap_or(self.started_at),
+            },
+            status => ExecutionEvent::Status {
+                status: status.clone(),
+                started_at: self.started_at,
+            },
+        }
     }
 
     pub fn duration_ms(&self) -> Option<u64> {
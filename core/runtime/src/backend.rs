@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Which WASM engine ran a given execution. Selectable per-`AgentRequest`
+/// via `AgentConfig::backend`; `WasmSandbox` defaults to `Wasmtime` when
+/// unset or unrecognized. Recorded on `ExecutionMetrics` so a result can
+/// always be traced back to the engine that produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// Cranelift-JIT wasmtime: the fastest option, but codegen can vary
+    /// subtly across host CPUs and JIT compilation is unavailable in
+    /// locked-down, no-JIT environments.
+    Wasmtime,
+    /// Pure-interpreter wasmi: trades throughput for byte-for-byte
+    /// deterministic execution and a smaller attack surface, which matters
+    /// when agent results must be reproducible across heterogeneous nodes.
+    Wasmi,
+}
+
+impl BackendKind {
+    /// Parse `AgentConfig::backend`; an absent or unrecognized name falls
+    /// back to the JIT backend rather than rejecting the request.
+    pub fn from_name(name: Option<&str>) -> Self {
+        match name {
+            Some("wasmi") => BackendKind::Wasmi,
+            _ => BackendKind::Wasmtime,
+        }
+    }
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Wasmtime
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::wasm::Capabilities;
+
+/// Per-execution host-function state every backend needs: authoritative
+/// call counters, the per-run KV store, and the granted capability set.
+/// Both the wasmtime and wasmi backends embed one of these in their own
+/// `Store` data type rather than redeclaring the same fields, so the
+/// capability checks and call accounting behind each host function only
+/// have one implementation to keep in lockstep across engines.
+pub(crate) struct HostState {
+    pub capabilities: Capabilities,
+    pub llm_calls: u32,
+    pub kv_operations: u32,
+    pub http_bytes_fetched: u64,
+    pub kv_store: HashMap<String, String>,
+}
+
+impl HostState {
+    pub fn new(capabilities: Capabilities) -> Self {
+        Self {
+            capabilities,
+            llm_calls: 0,
+            kv_operations: 0,
+            http_bytes_fetched: 0,
+            kv_store: HashMap::new(),
+        }
+    }
+}
+
+/// Shared decision logic behind `host_llm_call`. Engine-specific glue reads
+/// the prompt out of guest memory and writes the response back; this is the
+/// part that must behave identically regardless of which engine called it.
+pub(crate) fn llm_call(state: &mut HostState, prompt: String) -> Result<String> {
+    if !state.capabilities.contains(Capabilities::LLM) {
+        return Err(anyhow!("capability not granted: llm"));
+    }
+    state.llm_calls += 1;
+
+    // TODO: route to the orchestrator's real LLM provider. For now the call
+    // is accounted for authoritatively and echoes the prompt back.
+    Ok(prompt)
+}
+
+/// Shared decision logic behind `host_kv_get`.
+pub(crate) fn kv_get(state: &mut HostState, key: &str) -> Result<Option<String>> {
+    if !state.capabilities.contains(Capabilities::KV) {
+        return Err(anyhow!("capability not granted: kv"));
+    }
+    state.kv_operations += 1;
+    Ok(state.kv_store.get(key).cloned())
+}
+
+/// Shared decision logic behind `host_kv_put`.
+pub(crate) fn kv_put(state: &mut HostState, key: String, value: String) -> Result<()> {
+    if !state.capabilities.contains(Capabilities::KV) {
+        return Err(anyhow!("capability not granted: kv"));
+    }
+    state.kv_operations += 1;
+    state.kv_store.insert(key, value);
+    Ok(())
+}
+
+/// Shared decision logic behind `host_http_fetch`.
+pub(crate) fn http_fetch(state: &mut HostState, url: &str) -> Result<String> {
+    if !state.capabilities.contains(Capabilities::HTTP) {
+        return Err(anyhow!("capability not granted: http"));
+    }
+
+    // TODO: perform a real outbound fetch. Until then this still mediates
+    // and meters the call rather than granting raw network access.
+    let response =
+        format!(r#"{{"url":"{url}","status":501,"body":"http_fetch not yet implemented"}}"#);
+    state.http_bytes_fetched += response.len() as u64;
+    Ok(response)
+}
@@ -1,33 +1,77 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_nats::Client as NatsClient;
+use futures::future::AbortHandle;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch, Mutex, RwLock};
+use tokio::task::JoinSet;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use wasmtime::*;
 
+pub mod backend;
 pub mod wasm;
 pub mod agent;
+mod host_abi;
+pub mod registry;
 pub mod server;
+pub mod store;
+pub mod supervisor;
+pub mod wasmi_backend;
 
+pub use backend::BackendKind;
 pub use wasm::WasmSandbox;
-pub use agent::{AgentExecution, AgentRequest, AgentResponse};
+pub use agent::{AgentExecution, AgentRequest, AgentResponse, ExecutionEvent};
 pub use server::RuntimeServer;
+pub use store::{ExecutionStore, InMemoryExecutionStore, SqliteExecutionStore};
+pub use supervisor::{ExecutionOutcome, Supervisor, SupervisorMetrics};
+
+/// How many past events a late SSE/WebSocket subscriber's channel buffers
+/// before the sender starts lagging it.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// How many client-to-agent messages a WebSocket session's input channel
+/// buffers before `send_client_input` starts rejecting new ones. Bounding
+/// this is the backpressure policy: a client that writes faster than the
+/// sandbox can drain just gets errors back, instead of memory growing
+/// unbounded or the execution stalling on an unbounded channel.
+const INPUT_CHANNEL_CAPACITY: usize = 32;
 
 #[derive(Debug, Clone)]
 pub struct RuntimeConfig {
     pub port: u16,
     pub nats_url: String,
     pub orchestrator_url: String,
+    /// How long `shutdown()` waits for in-flight executions to finish on
+    /// their own before cancelling whatever is left.
+    pub shutdown_drain_timeout_secs: u64,
+    /// SQLite connection string (e.g. `sqlite://agentflow.db`) for durable
+    /// execution history. `None` keeps executions in memory only.
+    pub database_url: Option<String>,
+    /// Maximum number of agent executions run concurrently; excess requests
+    /// queue behind the supervisor instead of all spawning at once.
+    pub max_concurrency: usize,
 }
 
 pub struct AgentflowRuntime {
     config: RuntimeConfig,
     nats_client: NatsClient,
-    executions: Arc<RwLock<HashMap<String, AgentExecution>>>,
+    store: Arc<dyn ExecutionStore>,
+    event_channels: Arc<RwLock<HashMap<String, broadcast::Sender<ExecutionEvent>>>>,
+    /// Client-to-agent input for WebSocket sessions, keyed by execution id;
+    /// fed into the sandbox as a host import.
+    input_channels: Arc<RwLock<HashMap<String, mpsc::Sender<String>>>>,
+    cancel_handles: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    /// Tasks currently executing agent requests, so shutdown can await them.
+    in_flight: Arc<Mutex<JoinSet<()>>>,
+    /// Flips to `true` to tell the NATS subscriber loop and the HTTP server
+    /// to stop accepting new work.
+    shutdown_tx: watch::Sender<bool>,
     wasm_sandbox: Arc<WasmSandbox>,
+    supervisor: Arc<Supervisor>,
+    started_at: Instant,
 }
 
 impl AgentflowRuntime {
@@ -45,11 +89,41 @@ impl AgentflowRuntime {
         let wasm_sandbox = Arc::new(WasmSandbox::new()?);
         info!("✅ WASM sandbox initialized");
 
+        // Set up the execution store and rehydrate history from a previous run
+        let store: Arc<dyn ExecutionStore> = match &config.database_url {
+            Some(database_url) => {
+                info!("💾 Using SQLite execution store at {}", database_url);
+                Arc::new(SqliteExecutionStore::connect(database_url).await?)
+            }
+            None => {
+                info!("💾 Using in-memory execution store (no --database-url set)");
+                Arc::new(InMemoryExecutionStore::new())
+            }
+        };
+        store::mark_interrupted_executions(&store)
+            .await
+            .context("Failed to reconcile execution state on startup")?;
+
+        let (shutdown_tx, _) = watch::channel(false);
+        let cancel_handles = Arc::new(RwLock::new(HashMap::new()));
+        let supervisor = Arc::new(Supervisor::new(
+            config.max_concurrency,
+            Arc::clone(&wasm_sandbox),
+            Arc::clone(&cancel_handles),
+        ));
+
         let runtime = Self {
             config,
             nats_client,
-            executions: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            event_channels: Arc::new(RwLock::new(HashMap::new())),
+            input_channels: Arc::new(RwLock::new(HashMap::new())),
+            cancel_handles,
+            in_flight: Arc::new(Mutex::new(JoinSet::new())),
+            shutdown_tx,
             wasm_sandbox,
+            supervisor,
+            started_at: Instant::now(),
         };
 
         // Subscribe to agent execution requests
@@ -64,17 +138,42 @@ impl AgentflowRuntime {
             .await
             .context("Failed to subscribe to agent execution requests")?;
 
-        let executions = Arc::clone(&self.executions);
-        let wasm_sandbox = Arc::clone(&self.wasm_sandbox);
-        
+        let store = Arc::clone(&self.store);
+        let event_channels = Arc::clone(&self.event_channels);
+        let input_channels = Arc::clone(&self.input_channels);
+        let supervisor = Arc::clone(&self.supervisor);
+        let in_flight = Arc::clone(&self.in_flight);
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
         tokio::spawn(async move {
-            while let Some(message) = subscriber.next().await {
-                if let Err(e) = Self::handle_agent_request(
-                    message,
-                    Arc::clone(&executions),
-                    Arc::clone(&wasm_sandbox),
-                ).await {
-                    error!("Failed to handle agent request: {}", e);
+            loop {
+                tokio::select! {
+                    message = subscriber.next() => {
+                        let Some(message) = message else { break };
+
+                        let store = Arc::clone(&store);
+                        let event_channels = Arc::clone(&event_channels);
+                        let input_channels = Arc::clone(&input_channels);
+                        let supervisor = Arc::clone(&supervisor);
+
+                        in_flight.lock().await.spawn(async move {
+                            if let Err(e) = Self::handle_agent_request(
+                                message,
+                                store,
+                                event_channels,
+                                input_channels,
+                                supervisor,
+                            ).await {
+                                error!("Failed to handle agent request: {}", e);
+                            }
+                        });
+                    }
+                    _ = shutdown_rx.changed() => {
+                        if *shutdown_rx.borrow() {
+                            info!("📡 NATS subscriber loop unwinding for shutdown");
+                            break;
+                        }
+                    }
                 }
             }
         });
@@ -85,88 +184,228 @@ impl AgentflowRuntime {
 
     async fn handle_agent_request(
         message: async_nats::Message,
-        executions: Arc<RwLock<HashMap<String, AgentExecution>>>,
-        wasm_sandbox: Arc<WasmSandbox>,
+        store: Arc<dyn ExecutionStore>,
+        event_channels: Arc<RwLock<HashMap<String, broadcast::Sender<ExecutionEvent>>>>,
+        input_channels: Arc<RwLock<HashMap<String, mpsc::Sender<String>>>>,
+        supervisor: Arc<Supervisor>,
     ) -> Result<()> {
         let request: AgentRequest = serde_json::from_slice(&message.payload)
             .context("Failed to deserialize agent request")?;
 
         debug!("🤖 Received agent execution request: {}", request.agent_id);
 
-        // Create agent execution
-        let execution = AgentExecution::new(request.clone());
+        // Create agent execution and its event broadcast channel
+        let mut execution = AgentExecution::new(request.clone());
         let execution_id = execution.id.clone();
-        
-        // Store execution
-        executions.write().await.insert(execution_id.clone(), execution.clone());
+        let (events_tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
 
-        // Execute agent in WASM sandbox
-        let result = wasm_sandbox.execute_agent(request).await;
-        
-        // Update execution with result
-        {
-            let mut executions_write = executions.write().await;
-            if let Some(exec) = executions_write.get_mut(&execution_id) {
-                match result {
-                    Ok(response) => exec.complete_success(response),
-                    Err(e) => exec.complete_error(e.to_string()),
-                }
+        execution.start(&events_tx);
+
+        store.insert(&execution).await?;
+        event_channels.write().await.insert(execution_id.clone(), events_tx.clone());
+
+        // A WebSocket session can feed this execution input while it runs;
+        // the sandbox gets the receiving end as a host import.
+        let (input_tx, input_rx) = mpsc::channel(INPUT_CHANNEL_CAPACITY);
+        input_channels.write().await.insert(execution_id.clone(), input_tx);
+        let input_rx = Arc::new(Mutex::new(input_rx));
+
+        // Hand off to the supervisor, which bounds concurrency and retries
+        // failed/timed-out attempts before giving us a final outcome.
+        let outcome = supervisor
+            .submit(execution_id.clone(), execution.started_at, request, events_tx.clone(), input_rx)
+            .await?
+            .await
+            .unwrap_or_else(|_| {
+                ExecutionOutcome::Finished(Err(anyhow!(
+                    "supervisor dropped execution {}",
+                    execution_id
+                )))
+            });
+
+        // Update execution with result, persist it, and publish the
+        // transition to subscribers
+        match outcome {
+            ExecutionOutcome::Cancelled => execution.cancel(&events_tx),
+            ExecutionOutcome::Finished(Ok(response)) if response.success => {
+                execution.complete_success(response, &events_tx)
+            }
+            // A trapped/timed-out/fuel-exhausted guest still comes back as
+            // `Ok(AgentResult)`, just with `success: false`; treat it as a
+            // failure rather than letting it report as Completed, but keep
+            // its metrics instead of discarding them.
+            ExecutionOutcome::Finished(Ok(response)) => {
+                execution.complete_failure(response, &events_tx)
             }
+            ExecutionOutcome::Finished(Err(e)) => execution.complete_error(e.to_string(), &events_tx),
         }
+        store.update_status(&execution).await?;
+        input_channels.write().await.remove(&execution_id);
+        // The terminal event above has already been published to anyone
+        // subscribed; drop the channel now instead of leaking an entry per
+        // execution for the rest of the process's life. `watch_execution`
+        // falls back to an already-closed receiver when it finds no entry,
+        // so late SSE/WebSocket subscribers still see the stored snapshot.
+        event_channels.write().await.remove(&execution_id);
 
         // Send response back via NATS if reply subject is provided
         if let Some(reply) = message.reply {
-            let execution_read = executions.read().await;
-            if let Some(exec) = execution_read.get(&execution_id) {
-                let response = AgentResponse {
-                    execution_id: exec.id.clone(),
-                    agent_id: exec.request.agent_id.clone(),
-                    status: exec.status.clone(),
-                    result: exec.result.clone(),
-                    error: exec.error.clone(),
-                    started_at: exec.started_at,
-                    completed_at: exec.completed_at,
-                };
-
-                let response_bytes = serde_json::to_vec(&response)
-                    .context("Failed to serialize agent response")?;
-                
-                if let Err(e) = message.respond(response_bytes.into()).await {
-                    warn!("Failed to send agent response: {}", e);
-                }
+            let response = AgentResponse {
+                execution_id: execution.id.clone(),
+                agent_id: execution.request.agent_id.clone(),
+                status: execution.status.clone(),
+                result: execution.result.clone(),
+                error: execution.error.clone(),
+                started_at: execution.started_at,
+                completed_at: execution.completed_at,
+            };
+
+            let response_bytes = serde_json::to_vec(&response)
+                .context("Failed to serialize agent response")?;
+
+            if let Err(e) = message.respond(response_bytes.into()).await {
+                warn!("Failed to send agent response: {}", e);
             }
         }
 
         Ok(())
     }
 
-    pub async fn start_server(&self) -> Result<()> {
-        let server = RuntimeServer::new(
-            self.config.port,
-            Arc::clone(&self.executions),
-        );
-        
-        server.start().await
+    pub async fn start_server(self: Arc<Self>) -> Result<()> {
+        let port = self.config.port;
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        let server = RuntimeServer::new(port, Arc::clone(&self));
+
+        server.start(shutdown_rx).await
     }
 
+    /// Stop accepting new work and let executions already in flight finish,
+    /// rather than dropping them mid-run. Anything still running after
+    /// `shutdown_drain_timeout_secs` is cancelled outright.
     pub async fn shutdown(&self) -> Result<()> {
         info!("🛑 Shutting down AgentFlow Runtime...");
-        
-        // Cancel all running executions
-        let executions = self.executions.read().await;
-        for execution in executions.values() {
-            execution.cancel().await;
+
+        // Tell the NATS subscriber loop (and the HTTP server) to stop
+        // accepting new work; existing requests keep running.
+        let _ = self.shutdown_tx.send(true);
+
+        let drain_timeout = Duration::from_secs(self.config.shutdown_drain_timeout_secs);
+        let drained = tokio::time::timeout(drain_timeout, async {
+            let mut in_flight = self.in_flight.lock().await;
+            while in_flight.join_next().await.is_some() {}
+        })
+        .await;
+
+        if drained.is_err() {
+            warn!(
+                "⏱️ Drain timeout of {}s exceeded, cancelling remaining executions",
+                self.config.shutdown_drain_timeout_secs
+            );
+            for handle in self.cancel_handles.write().await.drain() {
+                handle.1.abort();
+            }
+            self.wasm_sandbox.interrupt_all();
         }
-        
+
+        // Reflect whatever is still marked running (only possible if the
+        // drain timed out) as cancelled, persist it, and notify subscribers.
+        let event_channels = self.event_channels.read().await;
+        for mut execution in self.store.list().await? {
+            if execution.is_running() {
+                if let Some(events_tx) = event_channels.get(&execution.id) {
+                    execution.cancel(events_tx);
+                    self.store.update_status(&execution).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cancel a single in-flight execution: abort its host future and bump
+    /// the WASM engine epoch so a guest stuck in a tight loop is interrupted
+    /// too, rather than just the host task being dropped.
+    pub async fn cancel_execution(&self, id: &str) -> Result<()> {
+        let handle = self
+            .cancel_handles
+            .write()
+            .await
+            .remove(id)
+            .ok_or_else(|| anyhow!("no running execution {}", id))?;
+
+        handle.abort();
+        self.wasm_sandbox.interrupt_all();
+
         Ok(())
     }
 
+    /// Feed a message from an interactive WebSocket client into a running
+    /// execution's sandbox. Uses `try_send` rather than `send` so a client
+    /// that writes faster than the sandbox drains its input backs off with
+    /// an error instead of stalling the WebSocket's inbound loop.
+    pub async fn send_client_input(&self, id: &str, message: String) -> Result<()> {
+        let input_channels = self.input_channels.read().await;
+        let sender = input_channels
+            .get(id)
+            .ok_or_else(|| anyhow!("no running execution {}", id))?;
+
+        sender
+            .try_send(message)
+            .map_err(|_| anyhow!("input backlog full for execution {}, dropping message", id))
+    }
+
     pub async fn get_execution(&self, id: &str) -> Option<AgentExecution> {
-        self.executions.read().await.get(id).cloned()
+        self.store.get(id).await.ok().flatten()
     }
 
     pub async fn list_executions(&self) -> Vec<AgentExecution> {
-        self.executions.read().await.values().cloned().collect()
+        self.store.list().await.unwrap_or_default()
+    }
+
+    pub fn supervisor_metrics(&self) -> SupervisorMetrics {
+        self.supervisor.metrics()
+    }
+
+    /// Seconds since this runtime process started, for the `/metrics` route.
+    pub fn uptime_seconds(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    /// Sum of every known execution's reported `memory_usage` metric, in
+    /// bytes, for the `/metrics` route.
+    pub async fn total_memory_usage_bytes(&self) -> u64 {
+        self.store
+            .list()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|e| e.result.as_ref())
+            .map(|r| r.metrics.memory_usage)
+            .sum()
+    }
+
+    /// Current state of an execution plus a receiver for its future events,
+    /// used by the SSE and WebSocket endpoints. An execution that has
+    /// already finished has no live channel (it's removed once its terminal
+    /// event is published), so callers get a receiver that's already closed
+    /// rather than `None` — the stored snapshot alone is a complete answer
+    /// for a finished execution.
+    pub async fn watch_execution(
+        &self,
+        id: &str,
+    ) -> Option<(AgentExecution, broadcast::Receiver<ExecutionEvent>)> {
+        // Subscribe before reading the stored snapshot: a transition
+        // published in the gap between the two would otherwise be missed by
+        // both the snapshot and the live subscription.
+        let receiver = match self.event_channels.read().await.get(id) {
+            Some(tx) => tx.subscribe(),
+            None => {
+                let (_tx, rx) = broadcast::channel(1);
+                rx
+            }
+        };
+        let execution = self.store.get(id).await.ok().flatten()?;
+        Some((execution, receiver))
     }
 }
 
@@ -174,6 +413,7 @@ impl AgentflowRuntime {
 pub enum ExecutionStatus {
     Pending,
     Running,
+    Retrying,
     Completed,
     Failed,
     Cancelled,
@@ -185,4 +425,16 @@ pub struct ExecutionMetrics {
     pub cpu_time: u64,
     pub llm_calls: u32,
     pub execution_time_ms: u64,
+    /// Fuel consumed by this execution, as metered by whichever engine ran
+    /// it (see `backend`); a deterministic,
+    /// platform-independent cost signal for billing and scheduling,
+    /// independent of the host machine's actual clock speed.
+    pub gas_used: u64,
+    /// Authoritative count of `host_kv_get`/`host_kv_put` calls.
+    pub kv_operations: u32,
+    /// Authoritative byte count returned by `host_http_fetch`.
+    pub http_bytes_fetched: u64,
+    /// Which WASM engine ran this execution: wasmtime (JIT) or wasmi
+    /// (deterministic interpreter).
+    pub backend: BackendKind,
 }
\ No newline at end of file
@@ -36,12 +36,35 @@ async fn main() -> anyhow::Result<()> {
                 .help("Orchestrator service URL")
                 .default_value("http://localhost:8080")
         )
+        .arg(
+            Arg::new("shutdown-drain-timeout")
+                .long("shutdown-drain-timeout")
+                .value_name("SECONDS")
+                .help("How long to wait for in-flight executions to finish on shutdown")
+                .default_value("30")
+        )
+        .arg(
+            Arg::new("database-url")
+                .long("database-url")
+                .value_name("URL")
+                .help("SQLite URL (e.g. sqlite://agentflow.db) for durable execution history; omit to keep executions in memory only")
+        )
+        .arg(
+            Arg::new("max-concurrency")
+                .long("max-concurrency")
+                .value_name("N")
+                .help("Maximum number of agent executions to run concurrently")
+                .default_value("8")
+        )
         .get_matches();
 
     let config = RuntimeConfig {
         port: matches.get_one::<String>("port").unwrap().parse()?,
         nats_url: matches.get_one::<String>("nats-url").unwrap().clone(),
         orchestrator_url: matches.get_one::<String>("orchestrator-url").unwrap().clone(),
+        shutdown_drain_timeout_secs: matches.get_one::<String>("shutdown-drain-timeout").unwrap().parse()?,
+        database_url: matches.get_one::<String>("database-url").cloned(),
+        max_concurrency: matches.get_one::<String>("max-concurrency").unwrap().parse()?,
     };
 
     info!("🦀 Starting AgentFlow Runtime on port {}", config.port);
@@ -66,10 +89,14 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    // Stop the server
-    server_handle.abort();
+    // Drain in-flight executions and signal the HTTP server to stop
+    // accepting new connections; it finishes whatever it's already serving.
     runtime.shutdown().await?;
-    
+
+    if let Err(e) = server_handle.await {
+        error!("Runtime server task panicked: {}", e);
+    }
+
     info!("✅ Runtime shutdown complete");
     Ok(())
 }
\ No newline at end of file
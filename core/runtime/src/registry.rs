@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+use wasmtime::{Engine, Module};
+
+/// Media types a registry may tag the agent's compiled WASM artifact with.
+const WASM_LAYER_MEDIA_TYPES: &[&str] = &[
+    "application/vnd.wasm.content.layer.v1+wasm",
+    "application/wasm",
+];
+
+/// A parsed `registry/repository[:tag|@digest]` reference, the same shape
+/// Docker/OCI image names use.
+#[derive(Debug, Clone)]
+struct ImageRef {
+    registry: String,
+    repository: String,
+    reference: String,
+}
+
+impl ImageRef {
+    fn parse(image: &str) -> Result<Self> {
+        let (registry, rest) = image
+            .split_once('/')
+            .ok_or_else(|| anyhow!("image reference '{image}' is missing a registry host"))?;
+
+        let (repository, reference) = if let Some((repo, digest)) = rest.split_once('@') {
+            (repo.to_string(), digest.to_string())
+        } else if let Some((repo, tag)) = rest.rsplit_once(':') {
+            (repo.to_string(), tag.to_string())
+        } else {
+            (rest.to_string(), "latest".to_string())
+        };
+
+        Ok(Self {
+            registry: registry.to_string(),
+            repository,
+            reference,
+        })
+    }
+}
+
+/// Compiled-module cache keyed by content digest: an in-memory LRU backed by
+/// an on-disk directory of `Module::serialize`d artifacts, so repeated
+/// executions of the same agent image skip Cranelift compilation. The
+/// manifest and WASM layer are still fetched from the registry on every
+/// load, since the layer's digest is the cache key.
+pub struct ModuleCache {
+    cache_dir: PathBuf,
+    memory: Mutex<lru::LruCache<String, Module>>,
+    http: reqwest::Client,
+}
+
+impl ModuleCache {
+    pub fn new(cache_dir: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        let cache_dir = cache_dir.into();
+        std::fs::create_dir_all(&cache_dir).with_context(|| {
+            format!("Failed to create module cache dir {}", cache_dir.display())
+        })?;
+
+        Ok(Self {
+            cache_dir,
+            memory: Mutex::new(lru::LruCache::new(
+                NonZeroUsize::new(capacity.max(1)).unwrap(),
+            )),
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// Resolve `image` to a compiled `Module`. The layer is pulled and
+    /// digest-verified on every call; it is compiled only on a cold cache.
+    pub async fn load(&self, engine: &Engine, image: &str) -> Result<Module> {
+        let image_ref = ImageRef::parse(image)?;
+        let (digest, wasm_bytes) = self.fetch_layer(&image_ref).await?;
+
+        if let Some(module) = self.memory.lock().unwrap().get(&digest).cloned() {
+            debug!("📦 Module cache hit (memory) for {}", digest);
+            return Ok(module);
+        }
+
+        let disk_path = self.disk_path(engine, &digest);
+        if disk_path.exists() {
+            match self.load_from_disk(engine, &disk_path) {
+                Some(module) => {
+                    debug!("📦 Module cache hit (disk) for {}", digest);
+                    self.memory.lock().unwrap().put(digest, module.clone());
+                    return Ok(module);
+                }
+                None => {
+                    warn!(
+                        "🗑️ Discarding incompatible cached module at {}",
+                        disk_path.display()
+                    );
+                    let _ = std::fs::remove_file(&disk_path);
+                }
+            }
+        }
+
+        info!(
+            "🛠️ Compiling agent module for {} ({} bytes)",
+            image,
+            wasm_bytes.len()
+        );
+        let module =
+            Module::new(engine, &wasm_bytes).context("Failed to compile agent WASM module")?;
+
+        if let Err(e) = self.store_to_disk(&module, &disk_path) {
+            warn!("Failed to persist compiled module cache for {}: {}", digest, e);
+        }
+        self.memory.lock().unwrap().put(digest, module.clone());
+
+        Ok(module)
+    }
+
+    /// Resolve `image` to raw WASM bytes without compiling or caching a
+    /// compiled module. Interpreter backends have no expensive compile step
+    /// worth caching, so they pull straight from the registry through this
+    /// instead of going through the compiled-module cache.
+    pub async fn fetch_bytes(&self, image: &str) -> Result<Vec<u8>> {
+        let image_ref = ImageRef::parse(image)?;
+        let (_digest, wasm_bytes) = self.fetch_layer(&image_ref).await?;
+        Ok(wasm_bytes)
+    }
+
+    /// Pull the image's manifest, find its WASM layer, fetch that blob, and
+    /// verify it against its advertised digest before handing it back.
+    async fn fetch_layer(&self, image_ref: &ImageRef) -> Result<(String, Vec<u8>)> {
+        let manifest_url = format!(
+            "https://{}/v2/{}/manifests/{}",
+            image_ref.registry, image_ref.repository, image_ref.reference
+        );
+
+        let manifest: serde_json::Value = self
+            .http
+            .get(&manifest_url)
+            .header("Accept", "application/vnd.oci.image.manifest.v1+json")
+            .send()
+            .await
+            .context("Failed to reach registry for manifest")?
+            .error_for_status()
+            .context("Registry rejected manifest request")?
+            .json()
+            .await
+            .context("Failed to parse OCI manifest")?;
+
+        let layers = manifest["layers"]
+            .as_array()
+            .ok_or_else(|| anyhow!("OCI manifest for {} has no layers", image_ref.repository))?;
+
+        let wasm_layer = layers
+            .iter()
+            .find(|layer| {
+                layer["mediaType"]
+                    .as_str()
+                    .map(|mt| WASM_LAYER_MEDIA_TYPES.contains(&mt))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| {
+                anyhow!("No WASM layer found in manifest for {}", image_ref.repository)
+            })?;
+
+        let digest = wasm_layer["digest"]
+            .as_str()
+            .ok_or_else(|| anyhow!("WASM layer is missing a digest"))?
+            .to_string();
+
+        let blob_url = format!(
+            "https://{}/v2/{}/blobs/{}",
+            image_ref.registry, image_ref.repository, digest
+        );
+
+        let bytes = self
+            .http
+            .get(&blob_url)
+            .send()
+            .await
+            .context("Failed to reach registry for blob")?
+            .error_for_status()
+            .context("Registry rejected blob request")?
+            .bytes()
+            .await
+            .context("Failed to download WASM layer")?;
+
+        self.verify_digest(&digest, &bytes)?;
+
+        Ok((digest, bytes.to_vec()))
+    }
+
+    fn verify_digest(&self, digest: &str, bytes: &[u8]) -> Result<()> {
+        let expected = digest
+            .strip_prefix("sha256:")
+            .ok_or_else(|| anyhow!("unsupported digest algorithm in '{digest}'"))?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        let actual = to_hex(&hasher.finalize());
+
+        if actual != expected {
+            return Err(anyhow!(
+                "WASM layer digest mismatch: manifest says {}, downloaded blob hashes to {}",
+                expected,
+                actual
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Load a cached compiled module. Returns `None` rather than trusting it
+    /// if the artifact was produced by an incompatible engine version.
+    fn load_from_disk(&self, engine: &Engine, path: &Path) -> Option<Module> {
+        let bytes = std::fs::read(path).ok()?;
+        // SAFETY: only ever deserializes artifacts this process itself wrote
+        // via `Module::serialize`, under a path already namespaced by this
+        // engine's compatibility hash.
+        unsafe { Module::deserialize(engine, bytes).ok() }
+    }
+
+    fn store_to_disk(&self, module: &Module, path: &Path) -> Result<()> {
+        let bytes = module
+            .serialize()
+            .context("Failed to serialize compiled module")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, bytes).context("Failed to write compiled module cache entry")
+    }
+
+    /// Cache path namespaced by the engine's compatibility hash, so a
+    /// wasmtime/engine upgrade naturally misses the cache instead of
+    /// deserializing (and rejecting) an incompatible artifact on every load.
+    fn disk_path(&self, engine: &Engine, digest: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let compat = format!("{:016x}", hasher.finish());
+
+        let file_name = digest.replace(':', "_");
+        self.cache_dir
+            .join(compat)
+            .join(format!("{file_name}.cwasm"))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
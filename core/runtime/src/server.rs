@@ -1,30 +1,37 @@
 use anyhow::Result;
+use futures::{SinkExt, StreamExt};
 use serde_json::json;
-use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, warn};
-use crate::agent::AgentExecution;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{debug, info, warn};
+use warp::sse::Event as SseEvent;
+use warp::ws::{Message as WsMessage, WebSocket};
+use crate::agent::{AgentExecution, ExecutionEvent};
+use crate::AgentflowRuntime;
+
+/// How many outgoing frames a WebSocket session buffers before the client is
+/// considered too slow and the connection is closed. This is the
+/// backpressure policy on the agent-to-client side.
+const WS_OUTBOUND_CAPACITY: usize = 32;
 
 pub struct RuntimeServer {
     port: u16,
-    executions: Arc<RwLock<HashMap<String, AgentExecution>>>,
+    runtime: Arc<AgentflowRuntime>,
 }
 
 impl RuntimeServer {
-    pub fn new(
-        port: u16,
-        executions: Arc<RwLock<HashMap<String, AgentExecution>>>,
-    ) -> Self {
-        Self { port, executions }
+    pub fn new(port: u16, runtime: Arc<AgentflowRuntime>) -> Self {
+        Self { port, runtime }
     }
 
-    pub async fn start(&self) -> Result<()> {
+    pub async fn start(&self, mut shutdown_rx: watch::Receiver<bool>) -> Result<()> {
         use warp::Filter;
 
         info!("🌐 Starting Runtime HTTP server on port {}", self.port);
 
-        let executions = Arc::clone(&self.executions);
+        let runtime = Arc::clone(&self.runtime);
 
         // GET /health - Health check
         let health = warp::path("health")
@@ -37,36 +44,33 @@ impl RuntimeServer {
             });
 
         // GET /executions - List all executions
-        let executions_clone = Arc::clone(&executions);
+        let runtime_clone = Arc::clone(&runtime);
         let list_executions = warp::path("executions")
             .and(warp::get())
             .and_then(move || {
-                let executions = Arc::clone(&executions_clone);
+                let runtime = Arc::clone(&runtime_clone);
                 async move {
-                    let executions_read = executions.read().await;
-                    let executions_list: Vec<_> = executions_read.values().cloned().collect();
-                    
+                    let executions_list = runtime.list_executions().await;
+
                     let response = json!({
                         "executions": executions_list,
                         "count": executions_list.len()
                     });
-                    
+
                     Ok::<_, warp::Rejection>(warp::reply::json(&response))
                 }
             });
 
         // GET /executions/{id} - Get specific execution
-        let executions_clone = Arc::clone(&executions);
+        let runtime_clone = Arc::clone(&runtime);
         let get_execution = warp::path!("executions" / String)
             .and(warp::get())
             .and_then(move |id: String| {
-                let executions = Arc::clone(&executions_clone);
+                let runtime = Arc::clone(&runtime_clone);
                 async move {
-                    let executions_read = executions.read().await;
-                    
-                    match executions_read.get(&id) {
+                    match runtime.get_execution(&id).await {
                         Some(execution) => {
-                            Ok(warp::reply::json(execution))
+                            Ok(warp::reply::json(&execution))
                         }
                         None => {
                             let response = json!({
@@ -79,35 +83,113 @@ impl RuntimeServer {
                 }
             });
 
+        // GET /executions/{id}/events - SSE stream of status transitions and output
+        let runtime_clone = Arc::clone(&runtime);
+        let stream_execution_events = warp::path!("executions" / String / "events")
+            .and(warp::get())
+            .and_then(move |id: String| {
+                let runtime = Arc::clone(&runtime_clone);
+                async move {
+                    let (snapshot, receiver) = match runtime.watch_execution(&id).await {
+                        Some(watch) => watch,
+                        None => return Err(warp::reject::not_found()),
+                    };
+
+                    debug!("📡 New SSE subscriber for execution {}", id);
+
+                    // Replay the current known state first so a client that
+                    // subscribes after the transition it cares about isn't stuck.
+                    let initial = futures::stream::once(async move {
+                        sse_event(&snapshot.snapshot_event())
+                    });
+
+                    let live = BroadcastStream::new(receiver).filter_map(|event| async move {
+                        match event {
+                            Ok(event) => Some(sse_event(&event)),
+                            // A slow subscriber missed some events; it will pick
+                            // up the latest state on the next one.
+                            Err(_) => None,
+                        }
+                    });
+
+                    let body = initial.chain(live);
+                    Ok::<_, warp::Rejection>(warp::sse::reply(warp::sse::keep_alive().stream(body)))
+                }
+            });
+
+        // GET /executions/{id}/ws - Duplex session: stream input in, output out
+        let runtime_clone = Arc::clone(&runtime);
+        let ws_session = warp::path!("executions" / String / "ws")
+            .and(warp::ws())
+            .and_then(move |id: String, ws: warp::ws::Ws| {
+                let runtime = Arc::clone(&runtime_clone);
+                async move {
+                    let (snapshot, receiver) = match runtime.watch_execution(&id).await {
+                        Some(watch) => watch,
+                        None => return Err(warp::reject::not_found()),
+                    };
+
+                    Ok::<_, warp::Rejection>(ws.on_upgrade(move |socket| {
+                        Self::handle_ws_session(socket, id, snapshot, receiver, runtime)
+                    }))
+                }
+            });
+
+        // POST /executions/{id}/cancel - Abort an in-flight execution
+        let runtime_clone = Arc::clone(&runtime);
+        let cancel_execution = warp::path!("executions" / String / "cancel")
+            .and(warp::post())
+            .and_then(move |id: String| {
+                let runtime = Arc::clone(&runtime_clone);
+                async move {
+                    match runtime.cancel_execution(&id).await {
+                        Ok(()) => Ok(warp::reply::json(&json!({
+                            "id": id,
+                            "status": "cancelling"
+                        }))),
+                        Err(e) => Ok(warp::reply::json(&json!({
+                            "error": e.to_string(),
+                            "id": id
+                        }))),
+                    }
+                }
+            });
+
         // GET /metrics - Runtime metrics
-        let executions_clone = Arc::clone(&executions);
+        let runtime_clone = Arc::clone(&runtime);
         let metrics = warp::path("metrics")
             .and(warp::get())
             .and_then(move || {
-                let executions = Arc::clone(&executions_clone);
+                let runtime = Arc::clone(&runtime_clone);
                 async move {
-                    let executions_read = executions.read().await;
-                    
-                    let total_executions = executions_read.len();
-                    let running_executions = executions_read
-                        .values()
+                    let executions_list = runtime.list_executions().await;
+
+                    let total_executions = executions_list.len();
+                    let running_executions = executions_list
+                        .iter()
                         .filter(|e| e.is_running())
                         .count();
-                    let completed_executions = executions_read
-                        .values()
+                    let completed_executions = executions_list
+                        .iter()
                         .filter(|e| e.is_completed())
                         .count();
 
+                    let supervisor_metrics = runtime.supervisor_metrics();
+                    let memory_usage_mb =
+                        runtime.total_memory_usage_bytes().await / (1024 * 1024);
+
                     let response = json!({
                         "runtime_metrics": {
                             "total_executions": total_executions,
                             "running_executions": running_executions,
                             "completed_executions": completed_executions,
-                            "memory_usage_mb": 256, // TODO: Get actual memory usage
-                            "uptime_seconds": 3600  // TODO: Track actual uptime
+                            "queue_depth": supervisor_metrics.queue_depth,
+                            "active_workers": supervisor_metrics.active_workers,
+                            "memory_usage_mb": memory_usage_mb,
+                            "uptime_seconds": runtime.uptime_seconds()
                         }
                     });
-                    
+
                     Ok::<_, warp::Rejection>(warp::reply::json(&response))
                 }
             });
@@ -116,16 +198,127 @@ impl RuntimeServer {
         let routes = health
             .or(list_executions)
             .or(get_execution)
+            .or(stream_execution_events)
+            .or(ws_session)
+            .or(cancel_execution)
             .or(metrics)
             .with(warp::cors().allow_any_origin());
 
-        // Start server
-        warp::serve(routes)
-            .run(([0, 0, 0, 0], self.port))
-            .await;
+        // Start server, refusing new connections once shutdown is signalled
+        // but letting in-flight ones finish.
+        let (_addr, server) = warp::serve(routes)
+            .bind_with_graceful_shutdown(([0, 0, 0, 0], self.port), async move {
+                while !*shutdown_rx.borrow() {
+                    if shutdown_rx.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+        server.await;
 
         Ok(())
     }
+
+    /// Drive one `/executions/{id}/ws` session: forward the execution's
+    /// events out to the client and any text frames the client sends in to
+    /// the sandbox, until the execution finishes or the client disconnects.
+    async fn handle_ws_session(
+        socket: WebSocket,
+        id: String,
+        snapshot: AgentExecution,
+        mut events: broadcast::Receiver<ExecutionEvent>,
+        runtime: Arc<AgentflowRuntime>,
+    ) {
+        debug!("🔌 New WebSocket session for execution {}", id);
+
+        let (mut ws_tx, mut ws_rx) = socket.split();
+
+        // Outbound: agent -> client. Bounded so a slow client backs up here
+        // rather than the broadcast channel lagging every other subscriber.
+        let (out_tx, mut out_rx) = mpsc::channel::<WsMessage>(WS_OUTBOUND_CAPACITY);
+        let _ = out_tx.try_send(ws_message(&snapshot.snapshot_event()));
+
+        let forward_id = id.clone();
+        let forward_task = tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let done = matches!(
+                    event,
+                    ExecutionEvent::Completed { .. }
+                        | ExecutionEvent::Failed { .. }
+                        | ExecutionEvent::Cancelled { .. }
+                );
+
+                if out_tx.try_send(ws_message(&event)).is_err() {
+                    warn!(
+                        "🐌 WebSocket client for execution {} fell behind, closing connection",
+                        forward_id
+                    );
+                    break;
+                }
+                if done {
+                    break;
+                }
+            }
+        });
+
+        let send_task = tokio::spawn(async move {
+            while let Some(message) = out_rx.recv().await {
+                if ws_tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+            let _ = ws_tx.close().await;
+        });
+
+        // Inbound: client -> agent, fed into the sandbox as host input.
+        while let Some(message) = ws_rx.next().await {
+            let Ok(message) = message else { break };
+            if let Ok(text) = message.to_str() {
+                if let Err(e) = runtime.send_client_input(&id, text.to_string()).await {
+                    debug!("Dropping WebSocket input for execution {}: {}", id, e);
+                }
+            }
+        }
+
+        let _ = forward_task.await;
+        let _ = send_task.await;
+    }
+}
+
+/// Encode an `ExecutionEvent` as an SSE frame: an `event:` line naming the
+/// variant and a `data:` line carrying its JSON payload.
+fn sse_event(event: &ExecutionEvent) -> Result<SseEvent, Infallible> {
+    let name = match event {
+        ExecutionEvent::Status { .. } => "status",
+        ExecutionEvent::Completed { .. } => "completed",
+        ExecutionEvent::Failed { .. } => "failed",
+        ExecutionEvent::Cancelled { .. } => "cancelled",
+    };
+
+    Ok(SseEvent::default()
+        .event(name)
+        .json_data(event)
+        .unwrap_or_else(|_| SseEvent::default().event("error").data("serialization failed")))
+}
+
+/// Encode an `ExecutionEvent` as a WebSocket JSON text frame tagged with a
+/// `type` the client can switch on: `status`, `error`, or `done` (collapsing
+/// both `completed` and `cancelled` into one terminal tag).
+fn ws_message(event: &ExecutionEvent) -> WsMessage {
+    let frame_type = match event {
+        ExecutionEvent::Status { .. } => "status",
+        ExecutionEvent::Failed { .. } => "error",
+        ExecutionEvent::Completed { .. } | ExecutionEvent::Cancelled { .. } => "done",
+    };
+
+    WsMessage::text(json!({ "type": frame_type, "event": event }).to_string())
 }
 
 // Note: This implementation uses warp, but since it's not in our Cargo.toml,
@@ -138,13 +331,13 @@ impl RuntimeServer {
         info!("🌐 Starting simple HTTP server on port {}", self.port);
 
         let listener = TcpListener::bind(format!("0.0.0.0:{}", self.port))?;
-        
+
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    let executions = Arc::clone(&self.executions);
+                    let runtime = Arc::clone(&self.runtime);
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(stream, executions).await {
+                        if let Err(e) = Self::handle_connection(stream, runtime).await {
                             warn!("Failed to handle connection: {}", e);
                         }
                     });
@@ -160,23 +353,20 @@ impl RuntimeServer {
 
     async fn handle_connection(
         mut stream: TcpStream,
-        executions: Arc<RwLock<HashMap<String, AgentExecution>>>,
+        runtime: Arc<AgentflowRuntime>,
     ) -> Result<()> {
         let mut buffer = [0; 1024];
         stream.read(&mut buffer)?;
 
         let request = String::from_utf8_lossy(&buffer[..]);
-        
+
         let response = if request.starts_with("GET /health") {
-            "HTTP/1.1 200 OK\r\n\r\n{\"status\": \"healthy\"}"
+            "HTTP/1.1 200 OK\r\n\r\n{\"status\": \"healthy\"}".to_string()
         } else if request.starts_with("GET /executions") {
-            let executions_read = executions.read().await;
-            let count = executions_read.len();
-            drop(executions_read);
-            
-            &format!("HTTP/1.1 200 OK\r\n\r\n{{\"count\": {}}}", count)
+            let count = runtime.list_executions().await.len();
+            format!("HTTP/1.1 200 OK\r\n\r\n{{\"count\": {}}}", count)
         } else {
-            "HTTP/1.1 404 NOT FOUND\r\n\r\n{\"error\": \"Not found\"}"
+            "HTTP/1.1 404 NOT FOUND\r\n\r\n{\"error\": \"Not found\"}".to_string()
         };
 
         stream.write_all(response.as_bytes())?;
@@ -184,4 +374,4 @@ impl RuntimeServer {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
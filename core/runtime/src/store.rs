@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::agent::AgentExecution;
+use crate::ExecutionStatus;
+
+/// Persists `AgentExecution` state so it survives a runtime restart, and
+/// backs the `/executions` HTTP endpoints.
+#[async_trait]
+pub trait ExecutionStore: Send + Sync {
+    async fn insert(&self, execution: &AgentExecution) -> Result<()>;
+    async fn update_status(&self, execution: &AgentExecution) -> Result<()>;
+    async fn get(&self, id: &str) -> Result<Option<AgentExecution>>;
+    async fn list(&self) -> Result<Vec<AgentExecution>>;
+}
+
+/// Default store used when no database is configured. Execution history is
+/// lost on restart, same as before this was pluggable.
+#[derive(Default)]
+pub struct InMemoryExecutionStore {
+    executions: RwLock<HashMap<String, AgentExecution>>,
+}
+
+impl InMemoryExecutionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ExecutionStore for InMemoryExecutionStore {
+    async fn insert(&self, execution: &AgentExecution) -> Result<()> {
+        self.executions
+            .write()
+            .await
+            .insert(execution.id.clone(), execution.clone());
+        Ok(())
+    }
+
+    async fn update_status(&self, execution: &AgentExecution) -> Result<()> {
+        self.executions
+            .write()
+            .await
+            .insert(execution.id.clone(), execution.clone());
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<AgentExecution>> {
+        Ok(self.executions.read().await.get(id).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<AgentExecution>> {
+        Ok(self.executions.read().await.values().cloned().collect())
+    }
+}
+
+/// SQLite-backed store, giving durable execution history and audit across
+/// restarts and redeploys.
+pub struct SqliteExecutionStore {
+    pool: SqlitePool,
+}
+
+impl SqliteExecutionStore {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        // Plain `.connect(url)` fails outright if the database file doesn't
+        // exist yet, which it never does on a fresh host — defeating the
+        // "survives restarts" point of a durable store on first boot.
+        let options = SqliteConnectOptions::from_str(database_url)
+            .context("Invalid SQLite database URL")?
+            .create_if_missing(true);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+            .context("Failed to connect to SQLite execution store")?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS executions (
+                id TEXT PRIMARY KEY,
+                request_json TEXT NOT NULL,
+                status TEXT NOT NULL,
+                result_json TEXT,
+                error TEXT,
+                started_at INTEGER NOT NULL,
+                completed_at INTEGER
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to run execution store migration")?;
+
+        Ok(Self { pool })
+    }
+
+    fn row_to_execution(row: &sqlx::sqlite::SqliteRow) -> Result<AgentExecution> {
+        let request_json: String = row.try_get("request_json")?;
+        let status_json: String = row.try_get("status")?;
+        let result_json: Option<String> = row.try_get("result_json")?;
+
+        Ok(AgentExecution {
+            id: row.try_get("id")?,
+            request: serde_json::from_str(&request_json)
+                .context("Failed to deserialize stored agent request")?,
+            status: serde_json::from_str(&status_json)
+                .context("Failed to deserialize stored execution status")?,
+            result: result_json
+                .map(|json| serde_json::from_str(&json))
+                .transpose()
+                .context("Failed to deserialize stored agent result")?,
+            error: row.try_get("error")?,
+            started_at: row.try_get::<i64, _>("started_at")? as u64,
+            completed_at: row
+                .try_get::<Option<i64>, _>("completed_at")?
+                .map(|v| v as u64),
+        })
+    }
+}
+
+#[async_trait]
+impl ExecutionStore for SqliteExecutionStore {
+    async fn insert(&self, execution: &AgentExecution) -> Result<()> {
+        let request_json = serde_json::to_string(&execution.request)?;
+        let status_json = serde_json::to_string(&execution.status)?;
+        let result_json = execution
+            .result
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO executions (id, request_json, status, result_json, error, started_at, completed_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                request_json = excluded.request_json,
+                status = excluded.status,
+                result_json = excluded.result_json,
+                error = excluded.error,
+                started_at = excluded.started_at,
+                completed_at = excluded.completed_at
+            "#,
+        )
+        .bind(&execution.id)
+        .bind(request_json)
+        .bind(status_json)
+        .bind(result_json)
+        .bind(&execution.error)
+        .bind(execution.started_at as i64)
+        .bind(execution.completed_at.map(|v| v as i64))
+        .execute(&self.pool)
+        .await
+        .context("Failed to insert execution")?;
+
+        Ok(())
+    }
+
+    async fn update_status(&self, execution: &AgentExecution) -> Result<()> {
+        // Same upsert as insert; executions are small enough that rewriting
+        // the whole row on every transition is simpler than a partial update.
+        self.insert(execution).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<AgentExecution>> {
+        let row = sqlx::query("SELECT * FROM executions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch execution")?;
+
+        row.as_ref().map(Self::row_to_execution).transpose()
+    }
+
+    async fn list(&self) -> Result<Vec<AgentExecution>> {
+        let rows = sqlx::query("SELECT * FROM executions ORDER BY started_at DESC")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list executions")?;
+
+        rows.iter().map(Self::row_to_execution).collect()
+    }
+}
+
+/// Executions that were `Running` when the runtime last stopped can't
+/// possibly still be; mark them `Failed` so status stays consistent.
+pub async fn mark_interrupted_executions(store: &Arc<dyn ExecutionStore>) -> Result<()> {
+    for mut execution in store.list().await? {
+        if execution.is_running() || matches!(execution.status, ExecutionStatus::Pending) {
+            execution.status = ExecutionStatus::Failed;
+            execution.error = Some("interrupted by runtime restart".to_string());
+            execution.completed_at = execution.completed_at.or(Some(execution.started_at));
+            store.update_status(&execution).await?;
+        }
+    }
+    Ok(())
+}
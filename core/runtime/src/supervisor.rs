@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use futures::future::{AbortHandle, Abortable};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock, Semaphore};
+use tracing::warn;
+
+use crate::agent::{AgentRequest, ExecutionEvent};
+use crate::wasm::{AgentResult, WasmSandbox};
+use crate::ExecutionStatus;
+
+/// Also the fallback used to derive the sandbox's epoch deadline (see
+/// `wasm::EPOCH_TICK_INTERVAL`), so the outer wall-clock timeout and the
+/// inner epoch-based one agree on what "no timeout specified" means.
+pub(crate) const DEFAULT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_RETRIES: u32 = 0;
+const QUEUE_CAPACITY: usize = 1024;
+const BASE_BACKOFF: Duration = Duration::from_millis(250);
+
+struct Job {
+    execution_id: String,
+    started_at: u64,
+    request: AgentRequest,
+    events_tx: broadcast::Sender<ExecutionEvent>,
+    /// Client-to-agent messages from an interactive WebSocket session, if
+    /// any is attached. Shared (rather than moved) across retries since each
+    /// attempt needs the same receiving end.
+    input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    respond_to: oneshot::Sender<ExecutionOutcome>,
+}
+
+/// Final disposition of a queued job once retries (if any) are exhausted.
+/// Kept distinct from a plain `Result<AgentResult>` so an explicit
+/// cancellation can't be mistaken for an ordinary failure by the caller:
+/// the two need different terminal statuses (`Cancelled` vs `Failed`).
+pub enum ExecutionOutcome {
+    Finished(Result<AgentResult>),
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SupervisorMetrics {
+    pub queue_depth: usize,
+    pub active_workers: usize,
+}
+
+/// Bounded-concurrency front door for WASM execution. Requests queue behind
+/// an mpsc channel and a semaphore caps how many run at once, so a burst of
+/// NATS messages queues up instead of spawning unboundedly and exhausting
+/// memory/CPU. Honors each request's `timeout_seconds` and retries failed
+/// or timed-out attempts up to `max_retries` with exponential backoff.
+pub struct Supervisor {
+    queue_tx: mpsc::Sender<Job>,
+    queue_depth: Arc<AtomicUsize>,
+    active_workers: Arc<AtomicUsize>,
+}
+
+impl Supervisor {
+    pub fn new(
+        max_concurrency: usize,
+        wasm_sandbox: Arc<WasmSandbox>,
+        cancel_handles: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    ) -> Self {
+        let (queue_tx, mut queue_rx) = mpsc::channel::<Job>(QUEUE_CAPACITY);
+        let queue_depth = Arc::new(AtomicUsize::new(0));
+        let active_workers = Arc::new(AtomicUsize::new(0));
+        let semaphore = Arc::new(Semaphore::new(max_concurrency.max(1)));
+
+        let dispatch_active = Arc::clone(&active_workers);
+        let dispatch_queue_depth = Arc::clone(&queue_depth);
+
+        tokio::spawn(async move {
+            while let Some(job) = queue_rx.recv().await {
+                dispatch_queue_depth.fetch_sub(1, Ordering::SeqCst);
+
+                let permit = Arc::clone(&semaphore)
+                    .acquire_owned()
+                    .await
+                    .expect("supervisor semaphore should never be closed");
+
+                let wasm_sandbox = Arc::clone(&wasm_sandbox);
+                let cancel_handles = Arc::clone(&cancel_handles);
+                let active_workers = Arc::clone(&dispatch_active);
+
+                active_workers.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    Self::run_job(job, wasm_sandbox, cancel_handles).await;
+                    active_workers.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Self { queue_tx, queue_depth, active_workers }
+    }
+
+    /// Queue an execution and return a receiver that resolves once a worker
+    /// has run it to completion (including any retries).
+    pub async fn submit(
+        &self,
+        execution_id: String,
+        started_at: u64,
+        request: AgentRequest,
+        events_tx: broadcast::Sender<ExecutionEvent>,
+        input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    ) -> Result<oneshot::Receiver<ExecutionOutcome>> {
+        let (respond_to, receiver) = oneshot::channel();
+
+        self.queue_depth.fetch_add(1, Ordering::SeqCst);
+        self.queue_tx
+            .send(Job { execution_id, started_at, request, events_tx, input_rx, respond_to })
+            .await
+            .map_err(|_| anyhow!("supervisor queue is closed"))?;
+
+        Ok(receiver)
+    }
+
+    pub fn metrics(&self) -> SupervisorMetrics {
+        SupervisorMetrics {
+            queue_depth: self.queue_depth.load(Ordering::SeqCst),
+            active_workers: self.active_workers.load(Ordering::SeqCst),
+        }
+    }
+
+    async fn run_job(
+        job: Job,
+        wasm_sandbox: Arc<WasmSandbox>,
+        cancel_handles: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    ) {
+        let Job { execution_id, started_at, request, events_tx, input_rx, respond_to } = job;
+
+        let timeout = Duration::from_secs(
+            request.config.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECS),
+        );
+        let max_retries = request.config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+
+        let mut last_error = anyhow!("execution never ran");
+        // The most recent guest-level failure (trap, fuel exhaustion, guest
+        // timeout), kept so that if retries run out we can still hand back
+        // the real AgentResult (metrics included) instead of collapsing it
+        // into a bare error string.
+        let mut last_result: Option<AgentResult> = None;
+
+        for attempt in 0..=max_retries {
+            // A fresh abort handle per attempt, so a cancel request always
+            // interrupts whichever attempt is currently running.
+            let (abort_handle, abort_registration) = AbortHandle::new_pair();
+            cancel_handles
+                .write()
+                .await
+                .insert(execution_id.clone(), abort_handle);
+
+            let attempt_result = tokio::time::timeout(
+                timeout,
+                Abortable::new(
+                    wasm_sandbox.execute_agent(request.clone(), Arc::clone(&input_rx)),
+                    abort_registration,
+                ),
+            )
+            .await;
+
+            cancel_handles.write().await.remove(&execution_id);
+
+            match attempt_result {
+                Ok(Ok(Ok(agent_result))) if agent_result.success => {
+                    let _ = respond_to.send(ExecutionOutcome::Finished(Ok(agent_result)));
+                    return;
+                }
+                // A trap, fuel exhaustion, or guest-side timeout comes back
+                // as `Ok(AgentResult { success: false, .. })` rather than an
+                // `Err`; treat it as retryable just like an infra error.
+                Ok(Ok(Ok(agent_result))) => {
+                    last_error = anyhow!(agent_result
+                        .error
+                        .clone()
+                        .unwrap_or_else(|| "agent execution failed".to_string()));
+                    last_result = Some(agent_result);
+                }
+                Ok(Ok(Err(e))) => last_error = e,
+                Ok(Err(_aborted)) => {
+                    let _ = respond_to.send(ExecutionOutcome::Cancelled);
+                    return;
+                }
+                Err(_elapsed) => {
+                    // The tokio timeout can't preempt the synchronous guest
+                    // call itself, so this is what actually stops it:
+                    // `interrupt_all` advances the engine epoch past every
+                    // live store's deadline, tripping the guest's epoch trap
+                    // on its very next check instead of leaving it to spin
+                    // until the background ticker independently catches up.
+                    wasm_sandbox.interrupt_all();
+                    last_error = anyhow!("execution timed out after {:?}", timeout)
+                }
+            }
+
+            if attempt < max_retries {
+                warn!(
+                    "🔁 Retrying execution {} (attempt {}/{}): {}",
+                    execution_id,
+                    attempt + 1,
+                    max_retries,
+                    last_error
+                );
+                let _ = events_tx.send(ExecutionEvent::Status {
+                    status: ExecutionStatus::Retrying,
+                    started_at,
+                });
+                tokio::time::sleep(BASE_BACKOFF * 2u32.pow(attempt)).await;
+            }
+        }
+
+        let outcome = match last_result {
+            Some(agent_result) => ExecutionOutcome::Finished(Ok(agent_result)),
+            None => ExecutionOutcome::Finished(Err(last_error)),
+        };
+        let _ = respond_to.send(outcome);
+    }
+}
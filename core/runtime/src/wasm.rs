@@ -1,181 +1,598 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use wasmtime::*;
 
+use crate::backend::BackendKind;
+use crate::host_abi::{self, HostState};
+use crate::registry::ModuleCache;
+use crate::supervisor::DEFAULT_TIMEOUT_SECS;
+
+/// Fuel seeded into every execution's `Store` before it runs; the amount
+/// consumed becomes the `cpu_time` metric, a platform-independent stand-in
+/// for wall-clock CPU usage.
+const DEFAULT_FUEL_BUDGET: u64 = 10_000_000;
+
+/// Minimum number of bytes requested from the guest's `alloc` for the input
+/// buffer, so agents with a fixed-size scratch allocator always get enough
+/// headroom regardless of how small the serialized input is. Shared with the
+/// wasmi backend so both engines apply the same floor.
+pub(crate) const MAX_RESULT_SIZE: i32 = 4096;
+
+/// Where compiled agent modules pulled from a registry are cached on disk,
+/// namespaced per engine compatibility hash by `ModuleCache` itself.
+const MODULE_CACHE_DIR: &str = "./.agentflow/module-cache";
+
+/// How many compiled modules `ModuleCache` keeps warm in memory; beyond this
+/// the least-recently-used module is evicted and has to be recompiled (or
+/// reloaded from disk) on its next use.
+const MODULE_CACHE_CAPACITY: usize = 64;
+
+/// How often the background epoch ticker bumps the engine epoch. A
+/// request's `timeout_seconds` is translated into a deadline tick count of
+/// `ceil(timeout / EPOCH_TICK_INTERVAL)`, so this is the timeout's actual
+/// resolution: a shorter interval enforces timeouts more precisely at the
+/// cost of waking up more often.
+const EPOCH_TICK_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct WasmSandbox {
     engine: Engine,
+    module_cache: ModuleCache,
+    /// Highest epoch-tick deadline (`deadline_ticks`, see
+    /// `execute_with_wasmtime`) any execution has been given. A plain
+    /// `increment_epoch()` only advances the epoch by one tick, which was
+    /// enough back when every store's deadline was a flat `1`; since a
+    /// store's deadline is now `epoch_at_start + deadline_ticks` (often in
+    /// the hundreds for a realistic timeout), one tick moves it only a
+    /// fraction of the way there. `interrupt_all` instead advances the
+    /// engine epoch by this high-water mark, which is always enough: for
+    /// any live store, `current_epoch >= epoch_at_start` (the engine epoch
+    /// only increases) and this value is `>= ` that store's own
+    /// `deadline_ticks`, so `current_epoch + high_water >= epoch_at_start +
+    /// deadline_ticks`, i.e. its deadline.
+    deadline_ticks_high_water: AtomicU64,
 }
 
 impl WasmSandbox {
     pub fn new() -> Result<Self> {
         let mut config = Config::new();
-        
+
         // Enable WebAssembly features for sandboxing
         config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
         config.wasm_multi_memory(true);
         config.wasm_memory64(false);
-        
+
         // Set resource limits for security
         config.epoch_interruption(true);
         config.max_wasm_stack(1024 * 1024); // 1MB stack limit
-        
+        config.consume_fuel(true);
+
         let engine = Engine::new(&config)
             .context("Failed to create WASM engine")?;
-        
-        Ok(Self { engine })
+        let module_cache = ModuleCache::new(MODULE_CACHE_DIR, MODULE_CACHE_CAPACITY)
+            .context("Failed to initialize agent module cache")?;
+
+        spawn_epoch_ticker(engine.clone());
+
+        Ok(Self {
+            engine,
+            module_cache,
+            deadline_ticks_high_water: AtomicU64::new(1),
+        })
     }
 
-    pub async fn execute_agent(&self, request: crate::agent::AgentRequest) -> Result<AgentResult> {
+    /// Advance the engine epoch past any live store's deadline, so a
+    /// cancelled or timed-out guest traps on its very next epoch check
+    /// instead of spinning until the background ticker independently
+    /// reaches its deadline. A single `increment_epoch()` isn't enough once
+    /// deadlines are set to a realistic multi-tick timeout rather than `1`
+    /// (see `deadline_ticks_high_water`), so this calls it as many times as
+    /// the largest deadline any execution has been given so far (the
+    /// high-water mark is never lowered, which only ever overshoots).
+    pub fn interrupt_all(&self) {
+        let ticks = self.deadline_ticks_high_water.load(Ordering::SeqCst).max(1);
+        for _ in 0..ticks {
+            self.engine.increment_epoch();
+        }
+    }
+
+    /// Dispatch to whichever engine `request.config.backend` selects. Both
+    /// backends share the same memory-marshalling contract and host-function
+    /// ABI, so the resulting `AgentOutput` is identical either way;
+    /// `ExecutionMetrics::backend` records which one ran, and the timing and
+    /// fuel figures come from that engine's own clock and metering.
+    pub async fn execute_agent(
+        &self,
+        request: crate::agent::AgentRequest,
+        input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    ) -> Result<AgentResult> {
+        match BackendKind::from_name(request.config.backend.as_deref()) {
+            BackendKind::Wasmtime => self.execute_with_wasmtime(request, input_rx).await,
+            BackendKind::Wasmi => {
+                let wasm_bytes = self.load_agent_wasm_bytes(&request.image).await?;
+                crate::wasmi_backend::execute_agent(&request, input_rx, &wasm_bytes).await
+            }
+        }
+    }
+
+    async fn execute_with_wasmtime(
+        &self,
+        request: crate::agent::AgentRequest,
+        input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    ) -> Result<AgentResult> {
         info!("🔒 Executing agent {} in WASM sandbox", request.agent_id);
-        
+
         // Create a new store for this execution
-        let mut store = Store::new(&self.engine, WasmContext::new());
-        
+        let capabilities = Capabilities::from_names(request.config.capabilities.as_deref().unwrap_or(&[]));
+        let mut store = Store::new(&self.engine, WasmContext::new(input_rx, capabilities));
+
         // Set resource limits
         store.limiter(|ctx| &mut ctx.limiter);
         store.epoch_deadline_trap();
-        
-        // Load the agent WASM module
-        let wasm_bytes = self.load_agent_wasm(&request.image).await?;
-        let module = Module::new(&self.engine, &wasm_bytes)
-            .context("Failed to compile WASM module")?;
-        
-        // Create instance
-        let instance = Instance::new(&mut store, &module, &[])
+        let fuel_limit = request.config.fuel_limit.unwrap_or(DEFAULT_FUEL_BUDGET);
+        store
+            .set_fuel(fuel_limit)
+            .context("Failed to seed fuel budget")?;
+
+        // Translate the request's wall-clock timeout into a deadline tick
+        // count so the epoch ticker actually enforces it, independent of
+        // (and a tighter bound than) the supervisor's own timeout.
+        let timeout = Duration::from_secs(
+            request.config.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECS),
+        );
+        let tick_ms = EPOCH_TICK_INTERVAL.as_millis().max(1) as u64;
+        let deadline_ticks = (timeout.as_millis() as u64).saturating_add(tick_ms - 1) / tick_ms;
+        let deadline_ticks = deadline_ticks.max(1);
+        store.set_epoch_deadline(deadline_ticks);
+        self.deadline_ticks_high_water
+            .fetch_max(deadline_ticks, Ordering::SeqCst);
+
+        // Resolve the agent's image to a compiled module. The registry is
+        // queried on every execution, but compilation happens only on the
+        // first one; later executions reuse the cached compiled artifact.
+        let module = self.load_agent_module(&request.image).await?;
+
+        // Give the guest a mediated, capability-gated host-function ABI
+        // instead of leaving it fully isolated (or trusting its
+        // self-reported metrics). Each function checks `WasmContext`'s
+        // granted capabilities and traps the call if the agent wasn't
+        // issued the one it needs.
+        let mut linker = Linker::new(&self.engine);
+
+        linker
+            .func_wrap(
+                "agentflow",
+                "host_llm_call",
+                |mut caller: Caller<'_, WasmContext>, prompt_ptr: i32, prompt_len: i32| -> Result<i64> {
+                    let prompt = read_guest_string(&mut caller, prompt_ptr, prompt_len)?;
+                    let response = host_abi::llm_call(&mut caller.data_mut().host, prompt)?;
+                    write_guest_string(&mut caller, &response)
+                },
+            )
+            .context("Failed to register host_llm_call import")?;
+
+        linker
+            .func_wrap(
+                "agentflow",
+                "host_kv_get",
+                |mut caller: Caller<'_, WasmContext>, key_ptr: i32, key_len: i32| -> Result<i64> {
+                    let key = read_guest_string(&mut caller, key_ptr, key_len)?;
+                    match host_abi::kv_get(&mut caller.data_mut().host, &key)? {
+                        Some(value) => write_guest_string(&mut caller, &value),
+                        // No entry: packed (offset 0, len 0) so the guest
+                        // can tell a miss from an empty string.
+                        None => Ok(0),
+                    }
+                },
+            )
+            .context("Failed to register host_kv_get import")?;
+
+        linker
+            .func_wrap(
+                "agentflow",
+                "host_kv_put",
+                |mut caller: Caller<'_, WasmContext>, key_ptr: i32, key_len: i32, val_ptr: i32, val_len: i32| -> Result<i32> {
+                    let key = read_guest_string(&mut caller, key_ptr, key_len)?;
+                    let value = read_guest_string(&mut caller, val_ptr, val_len)?;
+                    host_abi::kv_put(&mut caller.data_mut().host, key, value)?;
+                    Ok(1)
+                },
+            )
+            .context("Failed to register host_kv_put import")?;
+
+        linker
+            .func_wrap(
+                "agentflow",
+                "host_http_fetch",
+                |mut caller: Caller<'_, WasmContext>, url_ptr: i32, url_len: i32| -> Result<i64> {
+                    let url = read_guest_string(&mut caller, url_ptr, url_len)?;
+                    let response = host_abi::http_fetch(&mut caller.data_mut().host, &url)?;
+                    write_guest_string(&mut caller, &response)
+                },
+            )
+            .context("Failed to register host_http_fetch import")?;
+
+        // Lets a guest poll for a message from an interactive WebSocket
+        // client without blocking the store; returns 1 and buffers it in
+        // `last_input` if one is waiting, 0 otherwise.
+        linker
+            .func_wrap("agentflow", "host_receive_input", |mut caller: Caller<'_, WasmContext>| -> i32 {
+                let ctx = caller.data_mut();
+                let Ok(mut rx) = ctx.input_rx.try_lock() else {
+                    return 0;
+                };
+                match rx.try_recv() {
+                    Ok(message) => {
+                        ctx.last_input = Some(message);
+                        1
+                    }
+                    Err(_) => 0,
+                }
+            })
+            .context("Failed to register host_receive_input import")?;
+
+        let instance = linker
+            .instantiate(&mut store, &module)
             .context("Failed to instantiate WASM module")?;
-        
-        // Get the main execution function
+
+        // Get the main execution function. The guest returns a packed i64:
+        // high 32 bits are the result's offset in linear memory, low 32 bits
+        // its length.
         let execute_func = instance
-            .get_typed_func::<(i32, i32), i32>(&mut store, "execute")
+            .get_typed_func::<(i32, i32), i64>(&mut store, "execute")
             .context("Failed to get execute function from WASM module")?;
-        
+
         // Prepare input data
         let input_json = serde_json::to_string(&request.input)
             .context("Failed to serialize agent input")?;
-        
+
         let input_ptr = self.allocate_string(&mut store, &instance, &input_json)?;
-        let output_ptr = 0; // Will be set by the WASM function
-        
+        let input_len = input_json.len() as i32;
+
         debug!("🚀 Calling WASM execute function");
-        
+
         // Execute with timeout
         let start_time = SystemTime::now();
-        
-        // Set epoch deadline for timeout
-        store.set_epoch_deadline(1);
-        
-        let result = execute_func.call(&mut store, (input_ptr, output_ptr));
-        
+
+        let result = execute_func.call(&mut store, (input_ptr, input_len));
+
         let execution_time = start_time.elapsed().unwrap_or(Duration::ZERO);
-        
+
+        // Fuel consumed is our deterministic, platform-independent measure
+        // of work done: it doubles as the `cpu_time` proxy and the
+        // authoritative `gas_used` billing figure. Real memory usage comes
+        // straight from the instance's linear memory (0 if the module
+        // exports none).
+        let gas_used = fuel_limit.saturating_sub(store.get_fuel().unwrap_or(0));
+        let memory_usage = self.get_memory_usage(&mut store, &instance)?;
+        let llm_calls = store.data().host.llm_calls;
+        let kv_operations = store.data().host.kv_operations;
+        let http_bytes_fetched = store.data().host.http_bytes_fetched;
+
         match result {
             Ok(result_ptr) => {
                 debug!("✅ WASM execution completed successfully");
-                
+
                 // Read result from WASM memory
                 let output_json = self.read_string(&mut store, &instance, result_ptr)?;
                 let agent_output: AgentOutput = serde_json::from_str(&output_json)
                     .context("Failed to deserialize agent output")?;
-                
+
                 Ok(AgentResult {
                     success: true,
                     output: Some(agent_output),
                     error: None,
                     metrics: ExecutionMetrics {
                         execution_time_ms: execution_time.as_millis() as u64,
-                        memory_usage: self.get_memory_usage(&store, &instance)?,
-                        cpu_time: execution_time.as_millis() as u64,
-                        llm_calls: agent_output.llm_calls.unwrap_or(0),
+                        memory_usage,
+                        cpu_time: gas_used,
+                        llm_calls,
+                        gas_used,
+                        kv_operations,
+                        http_bytes_fetched,
+                        backend: BackendKind::Wasmtime,
                     },
                 })
             }
             Err(e) => {
-                error!("❌ WASM execution failed: {}", e);
-                
+                // Traps caused by the two independent kill-switches get
+                // distinct, deterministic errors instead of their generic
+                // trap message: fuel exhaustion mirrors the `GasLimit` error
+                // kind used by metered WASM runtimes, and an epoch deadline
+                // means the wall-clock timeout actually fired.
+                let error_message = match e.downcast_ref::<Trap>() {
+                    Some(Trap::OutOfFuel) => format!(
+                        "gas limit exceeded: consumed {} of {} fuel units",
+                        gas_used, fuel_limit
+                    ),
+                    Some(Trap::Interrupt) => format!(
+                        "execution timed out after {:?} ({} epoch ticks)",
+                        timeout, deadline_ticks
+                    ),
+                    _ => e.to_string(),
+                };
+                error!("❌ WASM execution failed: {}", error_message);
+
                 Ok(AgentResult {
                     success: false,
                     output: None,
-                    error: Some(e.to_string()),
+                    error: Some(error_message),
                     metrics: ExecutionMetrics {
                         execution_time_ms: execution_time.as_millis() as u64,
-                        memory_usage: 0,
-                        cpu_time: execution_time.as_millis() as u64,
-                        llm_calls: 0,
+                        memory_usage,
+                        cpu_time: gas_used,
+                        llm_calls,
+                        gas_used,
+                        kv_operations,
+                        http_bytes_fetched,
+                        backend: BackendKind::Wasmtime,
                     },
                 })
             }
         }
     }
 
-    async fn load_agent_wasm(&self, image: &str) -> Result<Vec<u8>> {
-        // TODO: Load WASM from container registry or local storage
-        // For now, return a minimal WASM module
-        info!("📦 Loading WASM module for image: {}", image);
-        
-        // This would typically download from a registry
-        // For demo purposes, we'll generate a minimal valid WASM module
-        Ok(self.generate_demo_wasm_module())
+    /// Resolve `image` as an OCI reference, pulling and compiling it through
+    /// the content-addressed `ModuleCache`. An image that isn't a resolvable
+    /// registry reference (or whose registry is unreachable) falls back to
+    /// the built-in demo module rather than failing the execution outright.
+    async fn load_agent_module(&self, image: &str) -> Result<Module> {
+        info!("📦 Resolving agent module for image: {}", image);
+
+        match self.module_cache.load(&self.engine, image).await {
+            Ok(module) => Ok(module),
+            Err(e) => {
+                warn!(
+                    "⚠️ Falling back to demo WASM module for image '{}': {}",
+                    image, e
+                );
+                Module::new(&self.engine, &self.generate_demo_wasm_module())
+                    .context("Failed to compile demo WASM module")
+            }
+        }
+    }
+
+    /// Same resolution as `load_agent_module`, but for backends (like the
+    /// wasmi interpreter) that instantiate straight from raw WASM bytes and
+    /// have no expensive compile step worth caching.
+    async fn load_agent_wasm_bytes(&self, image: &str) -> Result<Vec<u8>> {
+        match self.module_cache.fetch_bytes(image).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => {
+                warn!(
+                    "⚠️ Falling back to demo WASM module for image '{}': {}",
+                    image, e
+                );
+                Ok(self.generate_demo_wasm_module())
+            }
+        }
     }
 
     fn generate_demo_wasm_module(&self) -> Vec<u8> {
-        // Minimal WASM module with execute function
-        // This is a placeholder - in production, agents would be compiled to WASM
+        // Minimal WASM module implementing the real agent ABI: it exports
+        // `memory`, a bump-allocator `alloc(size) -> ptr`, and `execute(ptr,
+        // len) -> packed_result` that ignores its input and always returns a
+        // canned JSON result written into a data segment.
+        // This is a placeholder - in production, agents would be compiled to WASM.
         vec![
-            0x00, 0x61, 0x73, 0x6d, // WASM magic number
-            0x01, 0x00, 0x00, 0x00, // Version
-            // Type section
-            0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f,
-            // Function section  
-            0x03, 0x02, 0x01, 0x00,
-            // Export section
-            0x07, 0x0b, 0x01, 0x07, 0x65, 0x78, 0x65, 0x63, 0x75, 0x74, 0x65, 0x00, 0x00,
-            // Code section
-            0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // Type section: 0 = (i32) -> i32 [alloc], 1 = (i32, i32) -> i64 [execute]
+            0x01, 0x0c, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x02, 0x7f, 0x7f,
+            0x01, 0x7e,
+            // Function section: func 0 uses type 0, func 1 uses type 1
+            0x03, 0x03, 0x02, 0x00, 0x01,
+            // Memory section: one memory, min 1 page
+            0x05, 0x03, 0x01, 0x00, 0x01,
+            // Global section: mutable i32 bump-allocator pointer, init 4096
+            // (above the data segment, so input never overwrites the result)
+            0x06, 0x07, 0x01, 0x7f, 0x01, 0x41, 0x80, 0x20, 0x0b,
+            // Export section: memory, alloc, execute
+            0x07, 0x1c, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+            0x05, 0x61, 0x6c, 0x6c, 0x6f, 0x63, 0x00, 0x00, 0x07, 0x65, 0x78, 0x65,
+            0x63, 0x75, 0x74, 0x65, 0x00, 0x01,
+            // Code section: alloc bumps and returns the old pointer; execute
+            // returns a packed (offset << 32 | len) pointing at the data
+            // segment below, ignoring its arguments.
+            0x0a, 0x1b, 0x02, 0x0b, 0x00, 0x23, 0x00, 0x23, 0x00, 0x20, 0x00, 0x6a,
+            0x24, 0x00, 0x0b, 0x0d, 0x00, 0x41, 0x80, 0x10, 0xad, 0x42, 0x20, 0x86,
+            0x41, 0x34, 0xad, 0x84, 0x0b,
+            // Data section: `{"result":"Agent execution completed","llm_calls":0}` at offset 2048
+            0x0b, 0x3b, 0x01, 0x00, 0x41, 0x80, 0x10, 0x0b, 0x34, 0x7b, 0x22, 0x72,
+            0x65, 0x73, 0x75, 0x6c, 0x74, 0x22, 0x3a, 0x22, 0x41, 0x67, 0x65, 0x6e,
+            0x74, 0x20, 0x65, 0x78, 0x65, 0x63, 0x75, 0x74, 0x69, 0x6f, 0x6e, 0x20,
+            0x63, 0x6f, 0x6d, 0x70, 0x6c, 0x65, 0x74, 0x65, 0x64, 0x22, 0x2c, 0x22,
+            0x6c, 0x6c, 0x6d, 0x5f, 0x63, 0x61, 0x6c, 0x6c, 0x73, 0x22, 0x3a, 0x30,
+            0x7d,
         ]
     }
 
+    /// Ask the guest's `alloc` export for a buffer and write `s` into it.
     fn allocate_string(&self, store: &mut Store<WasmContext>, instance: &Instance, s: &str) -> Result<i32> {
-        // TODO: Implement proper memory allocation in WASM
-        // For now, return a mock pointer
         debug!("📝 Allocating string in WASM memory: {} bytes", s.len());
-        Ok(1000) // Mock pointer
+
+        let alloc_func = instance
+            .get_typed_func::<i32, i32>(&mut *store, "alloc")
+            .context("Agent module does not export alloc(i32) -> i32")?;
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("Agent module does not export memory")?;
+
+        let bytes = s.as_bytes();
+        let alloc_len = (bytes.len() as i32).max(MAX_RESULT_SIZE);
+        let offset = alloc_func
+            .call(&mut *store, alloc_len)
+            .context("Guest alloc() call failed")?;
+
+        memory
+            .write(&mut *store, offset as usize, bytes)
+            .context("Failed to write agent input into guest memory")?;
+
+        Ok(offset)
     }
 
-    fn read_string(&self, store: &mut Store<WasmContext>, instance: &Instance, ptr: i32) -> Result<String> {
-        // TODO: Implement proper memory reading from WASM
-        // For now, return mock output
-        debug!("📖 Reading string from WASM memory at pointer: {}", ptr);
-        
-        Ok(r#"{"result": "Agent execution completed", "llm_calls": 1}"#.to_string())
+    /// Unpack a guest-returned `(offset << 32) | length` value and read the
+    /// bytes it points to out of linear memory.
+    fn read_string(&self, store: &mut Store<WasmContext>, instance: &Instance, packed: i64) -> Result<String> {
+        let offset = (packed >> 32) as u32 as usize;
+        let len = (packed & 0xffff_ffff) as u32 as usize;
+
+        debug!("📖 Reading {} bytes from WASM memory at offset {}", len, offset);
+
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("Agent module does not export memory")?;
+
+        let mut bytes = vec![0u8; len];
+        memory
+            .read(&mut *store, offset, &mut bytes)
+            .context("Failed to read agent result from guest memory")?;
+
+        String::from_utf8(bytes).context("Agent result was not valid UTF-8")
     }
 
-    fn get_memory_usage(&self, store: &Store<WasmContext>, instance: &Instance) -> Result<u64> {
-        // TODO: Get actual memory usage from WASM instance
-        Ok(1024 * 1024) // Mock 1MB usage
+    fn get_memory_usage(&self, store: &mut Store<WasmContext>, instance: &Instance) -> Result<u64> {
+        match instance.get_memory(&mut *store, "memory") {
+            Some(memory) => Ok(memory.data_size(&store) as u64),
+            // An agent module that violates the ABI and skips a memory
+            // export still gets a metric instead of a hard failure.
+            None => Ok(0),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+/// `epoch_interruption` only traps a store once something actually
+/// increments the engine's epoch; this is that something. Without it
+/// `store.set_epoch_deadline(...)` is a no-op and a runaway guest spins
+/// forever. Runs for the engine's (and so the sandbox's) entire lifetime.
+fn spawn_epoch_ticker(engine: Engine) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EPOCH_TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            engine.increment_epoch();
+        }
+    });
+}
+
 struct WasmContext {
     limiter: StoreLimitsBuilder,
+    /// Capability checks, call accounting, and the KV store: shared with the
+    /// wasmi backend via `host_abi` so both engines enforce and meter the
+    /// same host-function ABI from one implementation.
+    host: HostState,
+    /// Client-to-agent messages from an interactive WebSocket session, fed to
+    /// the guest via `host_receive_input`.
+    input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    /// Most recent message popped off `input_rx` by `host_receive_input`.
+    /// No host import hands its contents to the guest; the guest only
+    /// learns whether a message arrived.
+    last_input: Option<String>,
 }
 
 impl WasmContext {
-    fn new() -> Self {
+    fn new(input_rx: Arc<Mutex<mpsc::Receiver<String>>>, capabilities: Capabilities) -> Self {
         let limiter = StoreLimitsBuilder::new()
             .memory_size(10 * 1024 * 1024) // 10MB memory limit
             .table_elements(1000)
             .instances(1)
             .tables(1)
             .memories(1);
-        
-        Self { limiter }
+
+        Self {
+            limiter,
+            host: HostState::new(capabilities),
+            input_rx,
+            last_input: None,
+        }
+    }
+}
+
+/// Least-privilege permission set granted to a single agent execution. An
+/// agent only gets the host functions its capability set covers; everything
+/// else traps rather than silently succeeding or falling back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    pub const LLM: Capabilities = Capabilities(1 << 0);
+    pub const KV: Capabilities = Capabilities(1 << 1);
+    pub const HTTP: Capabilities = Capabilities(1 << 2);
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Parse the capability names carried on `AgentConfig::capabilities`;
+    /// unrecognized names are ignored rather than rejected, so older clients
+    /// don't get an error for forward-looking names we don't support yet.
+    pub fn from_names(names: &[String]) -> Self {
+        names.iter().fold(Capabilities::NONE, |acc, name| {
+            let flag = match name.as_str() {
+                "llm" => Capabilities::LLM,
+                "kv" => Capabilities::KV,
+                "http" => Capabilities::HTTP,
+                _ => Capabilities::NONE,
+            };
+            acc | flag
+        })
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
     }
 }
 
+/// Read a UTF-8 string the guest placed in its own linear memory.
+fn read_guest_string(caller: &mut Caller<'_, WasmContext>, ptr: i32, len: i32) -> Result<String> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("Agent module does not export memory")?;
+
+    let mut bytes = vec![0u8; len as usize];
+    memory
+        .read(&mut *caller, ptr as usize, &mut bytes)
+        .context("Failed to read string from guest memory")?;
+
+    String::from_utf8(bytes).context("Guest string was not valid UTF-8")
+}
+
+/// Allocate space in the guest's own linear memory (via its `alloc` export)
+/// and write a host-produced string into it, returning the same packed
+/// `(offset << 32) | length` convention `execute` uses for its result.
+fn write_guest_string(caller: &mut Caller<'_, WasmContext>, s: &str) -> Result<i64> {
+    let alloc = caller
+        .get_export("alloc")
+        .and_then(Extern::into_func)
+        .context("Agent module does not export alloc")?
+        .typed::<i32, i32>(&mut *caller)
+        .context("Agent module's alloc has an unexpected signature")?;
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("Agent module does not export memory")?;
+
+    let bytes = s.as_bytes();
+    let offset = alloc
+        .call(&mut *caller, bytes.len() as i32)
+        .context("Guest alloc() call failed")?;
+    memory
+        .write(&mut *caller, offset as usize, bytes)
+        .context("Failed to write string into guest memory")?;
+
+    Ok(((offset as i64) << 32) | bytes.len() as i64)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentResult {
     pub success: bool,
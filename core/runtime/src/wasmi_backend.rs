@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, error};
+use wasmi::*;
+
+use crate::agent::AgentRequest;
+use crate::backend::BackendKind;
+use crate::host_abi::{self, HostState};
+use crate::wasm::{AgentOutput, AgentResult, Capabilities, MAX_RESULT_SIZE};
+use crate::ExecutionMetrics;
+
+/// Mirrors `wasm::DEFAULT_FUEL_BUDGET`: the interpreter backend shares the
+/// same fuel-based metering convention, just enforced by wasmi's own fuel
+/// consumption instead of wasmtime's.
+const DEFAULT_FUEL_BUDGET: u64 = 10_000_000;
+
+/// Run an agent module under the wasmi interpreter instead of wasmtime. The
+/// host-ABI surface (`host_llm_call`, `host_kv_get`/`put`, `host_http_fetch`,
+/// `host_receive_input`) mirrors the wasmtime backend's, and the capability
+/// checks and call accounting behind each of those are the exact same
+/// `host_abi` functions the wasmtime backend calls — only the engine-specific
+/// memory marshalling differs. Produces an identical `AgentResult`/
+/// `AgentOutput`, so callers can't tell which engine ran a request from its
+/// output alone.
+pub async fn execute_agent(
+    request: &AgentRequest,
+    input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    wasm_bytes: &[u8],
+) -> Result<AgentResult> {
+    debug!(
+        "🧮 Executing agent {} in the wasmi interpreter",
+        request.agent_id
+    );
+
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+
+    let module = Module::new(&engine, wasm_bytes).context("Failed to compile WASM module")?;
+
+    let capabilities = Capabilities::from_names(
+        request.config.capabilities.as_deref().unwrap_or(&[]),
+    );
+    let mut store = Store::new(&engine, WasmiContext::new(input_rx, capabilities));
+
+    let fuel_limit = request.config.fuel_limit.unwrap_or(DEFAULT_FUEL_BUDGET);
+    store
+        .set_fuel(fuel_limit)
+        .context("Failed to seed fuel budget")?;
+
+    let mut linker = Linker::new(&engine);
+
+    linker
+        .func_wrap(
+            "agentflow",
+            "host_llm_call",
+            |mut caller: Caller<'_, WasmiContext>, prompt_ptr: i32, prompt_len: i32| -> Result<i64> {
+                let prompt = read_guest_string(&mut caller, prompt_ptr, prompt_len)?;
+                let response = host_abi::llm_call(&mut caller.data_mut().host, prompt)?;
+                write_guest_string(&mut caller, &response)
+            },
+        )
+        .context("Failed to register host_llm_call import")?;
+
+    linker
+        .func_wrap(
+            "agentflow",
+            "host_kv_get",
+            |mut caller: Caller<'_, WasmiContext>, key_ptr: i32, key_len: i32| -> Result<i64> {
+                let key = read_guest_string(&mut caller, key_ptr, key_len)?;
+                match host_abi::kv_get(&mut caller.data_mut().host, &key)? {
+                    Some(value) => write_guest_string(&mut caller, &value),
+                    None => Ok(0),
+                }
+            },
+        )
+        .context("Failed to register host_kv_get import")?;
+
+    linker
+        .func_wrap(
+            "agentflow",
+            "host_kv_put",
+            |mut caller: Caller<'_, WasmiContext>,
+             key_ptr: i32,
+             key_len: i32,
+             val_ptr: i32,
+             val_len: i32|
+             -> Result<i32> {
+                let key = read_guest_string(&mut caller, key_ptr, key_len)?;
+                let value = read_guest_string(&mut caller, val_ptr, val_len)?;
+                host_abi::kv_put(&mut caller.data_mut().host, key, value)?;
+                Ok(1)
+            },
+        )
+        .context("Failed to register host_kv_put import")?;
+
+    linker
+        .func_wrap(
+            "agentflow",
+            "host_http_fetch",
+            |mut caller: Caller<'_, WasmiContext>, url_ptr: i32, url_len: i32| -> Result<i64> {
+                let url = read_guest_string(&mut caller, url_ptr, url_len)?;
+                let response = host_abi::http_fetch(&mut caller.data_mut().host, &url)?;
+                write_guest_string(&mut caller, &response)
+            },
+        )
+        .context("Failed to register host_http_fetch import")?;
+
+    linker
+        .func_wrap(
+            "agentflow",
+            "host_receive_input",
+            |mut caller: Caller<'_, WasmiContext>| -> i32 {
+                let ctx = caller.data_mut();
+                let Ok(mut rx) = ctx.input_rx.try_lock() else {
+                    return 0;
+                };
+                match rx.try_recv() {
+                    Ok(message) => {
+                        ctx.last_input = Some(message);
+                        1
+                    }
+                    Err(_) => 0,
+                }
+            },
+        )
+        .context("Failed to register host_receive_input import")?;
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .context("Failed to instantiate WASM module")?
+        .start(&mut store)
+        .context("Failed to run WASM module start function")?;
+
+    let execute_func = instance
+        .get_typed_func::<(i32, i32), i64>(&store, "execute")
+        .context("Failed to get execute function from WASM module")?;
+
+    let input_json =
+        serde_json::to_string(&request.input).context("Failed to serialize agent input")?;
+    let input_ptr = allocate_string(&mut store, &instance, &input_json)?;
+    let input_len = input_json.len() as i32;
+
+    let start_time = SystemTime::now();
+    let result = execute_func.call(&mut store, (input_ptr, input_len));
+    let execution_time = start_time.elapsed().unwrap_or(Duration::ZERO);
+
+    let gas_used = fuel_limit.saturating_sub(store.get_fuel().unwrap_or(0));
+    let memory_usage = match instance.get_memory(&store, "memory") {
+        Some(memory) => memory.data_size(&store) as u64,
+        None => 0,
+    };
+    let llm_calls = store.data().host.llm_calls;
+    let kv_operations = store.data().host.kv_operations;
+    let http_bytes_fetched = store.data().host.http_bytes_fetched;
+
+    match result {
+        Ok(result_ptr) => {
+            debug!("✅ wasmi execution completed successfully");
+
+            let output_json = read_string(&mut store, &instance, result_ptr)?;
+            let agent_output: AgentOutput = serde_json::from_str(&output_json)
+                .context("Failed to deserialize agent output")?;
+
+            Ok(AgentResult {
+                success: true,
+                output: Some(agent_output),
+                error: None,
+                metrics: ExecutionMetrics {
+                    execution_time_ms: execution_time.as_millis() as u64,
+                    memory_usage,
+                    cpu_time: gas_used,
+                    llm_calls,
+                    gas_used,
+                    kv_operations,
+                    http_bytes_fetched,
+                    backend: BackendKind::Wasmi,
+                },
+            })
+        }
+        Err(e) => {
+            // wasmi doesn't expose a dedicated out-of-fuel error type the
+            // way wasmtime's `Trap` does, so recognize the case by message
+            // instead of by downcast.
+            let error_message = if e.to_string().to_lowercase().contains("fuel") {
+                format!(
+                    "gas limit exceeded: consumed {} of {} fuel units",
+                    gas_used, fuel_limit
+                )
+            } else {
+                e.to_string()
+            };
+            error!("❌ wasmi execution failed: {}", error_message);
+
+            Ok(AgentResult {
+                success: false,
+                output: None,
+                error: Some(error_message),
+                metrics: ExecutionMetrics {
+                    execution_time_ms: execution_time.as_millis() as u64,
+                    memory_usage,
+                    cpu_time: gas_used,
+                    llm_calls,
+                    gas_used,
+                    kv_operations,
+                    http_bytes_fetched,
+                    backend: BackendKind::Wasmi,
+                },
+            })
+        }
+    }
+}
+
+struct WasmiContext {
+    /// Capability checks, call accounting, and the KV store: shared with the
+    /// wasmtime backend via `host_abi` so both engines enforce and meter the
+    /// same host-function ABI from one implementation.
+    host: HostState,
+    input_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    last_input: Option<String>,
+}
+
+impl WasmiContext {
+    fn new(input_rx: Arc<Mutex<mpsc::Receiver<String>>>, capabilities: Capabilities) -> Self {
+        Self {
+            host: HostState::new(capabilities),
+            input_rx,
+            last_input: None,
+        }
+    }
+}
+
+/// Ask the guest's `alloc` export for a buffer and write `s` into it. Same
+/// packed `(offset << 32) | length` convention the wasmtime backend uses,
+/// including the same `MAX_RESULT_SIZE` floor: a guest that reuses its input
+/// buffer to write a larger result must get the same headroom under wasmi
+/// that it gets under wasmtime.
+fn allocate_string(store: &mut Store<WasmiContext>, instance: &Instance, s: &str) -> Result<i32> {
+    let alloc_func = instance
+        .get_typed_func::<i32, i32>(&mut *store, "alloc")
+        .context("Agent module does not export alloc(i32) -> i32")?;
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .context("Agent module does not export memory")?;
+
+    let bytes = s.as_bytes();
+    let alloc_len = (bytes.len() as i32).max(MAX_RESULT_SIZE);
+    let offset = alloc_func
+        .call(&mut *store, alloc_len)
+        .context("Guest alloc() call failed")?;
+
+    memory
+        .write(&mut *store, offset as usize, bytes)
+        .context("Failed to write agent input into guest memory")?;
+
+    Ok(offset)
+}
+
+fn read_string(store: &mut Store<WasmiContext>, instance: &Instance, packed: i64) -> Result<String> {
+    let offset = (packed >> 32) as u32 as usize;
+    let len = (packed & 0xffff_ffff) as u32 as usize;
+
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .context("Agent module does not export memory")?;
+
+    let mut bytes = vec![0u8; len];
+    memory
+        .read(&mut *store, offset, &mut bytes)
+        .context("Failed to read agent result from guest memory")?;
+
+    String::from_utf8(bytes).context("Agent result was not valid UTF-8")
+}
+
+fn read_guest_string(caller: &mut Caller<'_, WasmiContext>, ptr: i32, len: i32) -> Result<String> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("Agent module does not export memory")?;
+
+    let mut bytes = vec![0u8; len as usize];
+    memory
+        .read(&mut *caller, ptr as usize, &mut bytes)
+        .context("Failed to read string from guest memory")?;
+
+    String::from_utf8(bytes).context("Guest string was not valid UTF-8")
+}
+
+fn write_guest_string(caller: &mut Caller<'_, WasmiContext>, s: &str) -> Result<i64> {
+    let alloc = caller
+        .get_export("alloc")
+        .and_then(Extern::into_func)
+        .context("Agent module does not export alloc")?
+        .typed::<i32, i32>(&mut *caller)
+        .context("Agent module's alloc has an unexpected signature")?;
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("Agent module does not export memory")?;
+
+    let bytes = s.as_bytes();
+    let offset = alloc
+        .call(&mut *caller, bytes.len() as i32)
+        .context("Guest alloc() call failed")?;
+    memory
+        .write(&mut *caller, offset as usize, bytes)
+        .context("Failed to write string into guest memory")?;
+
+    Ok(((offset as i64) << 32) | bytes.len() as i64)
+}